@@ -0,0 +1,36 @@
+// Builds the contract id of the option registry into the option contract.
+// OPTION_REGISTRY_ID must hold the 64 hex digits of the id the registry is
+// deployed at; the build fails without it, so options never point at a
+// placeholder registry.
+use std::{env, fs, path::Path};
+
+fn main() {
+    println!("cargo:rerun-if-env-changed=OPTION_REGISTRY_ID");
+    let id = env::var("OPTION_REGISTRY_ID")
+        .expect("set OPTION_REGISTRY_ID to the hex contract id of the option registry");
+    let id = parse_contract_id(id.trim())
+        .expect("OPTION_REGISTRY_ID must be a non-zero contract id of 64 hex digits");
+    let out = Path::new(&env::var("OUT_DIR").unwrap()).join("registry_id.rs");
+    fs::write(
+        out,
+        format!(
+            "pub(crate) const REGISTRY_CONTRACT_ID: [u8; 32] = {:?};\n",
+            id
+        ),
+    )
+    .unwrap();
+}
+
+fn parse_contract_id(hex: &str) -> Option<[u8; 32]> {
+    if hex.len() != 64 {
+        return None;
+    }
+    let mut id = [0u8; 32];
+    for (i, byte) in id.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(hex.get(i * 2..i * 2 + 2)?, 16).ok()?;
+    }
+    if id == [0; 32] {
+        return None;
+    }
+    Some(id)
+}
@@ -0,0 +1,74 @@
+use crate::errors::Error;
+use crate::registry;
+use crate::storage_types::{DataKey, INSTANCE_BUMP_AMOUNT};
+use soroban_sdk::{Address, BytesN, Env};
+
+// Contract id of the option registry holding the protocol admin, fees and
+// allow-lists, as REGISTRY_CONTRACT_ID. Sellers deploy and initialize their
+// own option contracts, so the registry is built into the code rather than
+// passed in; build.rs reads it from OPTION_REGISTRY_ID.
+include!(concat!(env!("OUT_DIR"), "/registry_id.rs"));
+
+pub fn registry_address(e: &Env) -> Address {
+    Address::from_contract_id(&BytesN::from_array(e, &REGISTRY_CONTRACT_ID))
+}
+
+fn registry_client(e: &Env) -> registry::Client<'_> {
+    registry::Client::new(e, &registry_address(e))
+}
+
+pub fn read_admin(e: &Env) -> Result<Address, Error> {
+    registry_client(e)
+        .try_admin()
+        .ok()
+        .and_then(|admin| admin.ok())
+        .ok_or(Error::NoAdmin)
+}
+
+pub fn read_fees(e: &Env) -> Result<registry::FeeConfig, Error> {
+    registry_client(e)
+        .try_fees()
+        .ok()
+        .and_then(|fees| fees.ok())
+        .ok_or(Error::NoAdmin)
+}
+
+// Whether the registry lists the oracle; denied if it cannot be asked
+pub fn read_oracle_allowed(e: &Env, oracle: &Address) -> bool {
+    registry_client(e)
+        .try_is_oracle_allowed(oracle)
+        .ok()
+        .and_then(|allowed| allowed.ok())
+        .unwrap_or(false)
+}
+
+// Whether the registry lists the escrow and underlying token pair
+pub fn read_pair_allowed(e: &Env, escrow_token: &Address, underlying_token: &Address) -> bool {
+    registry_client(e)
+        .try_is_pair_allowed(escrow_token, underlying_token)
+        .ok()
+        .and_then(|allowed| allowed.ok())
+        .unwrap_or(false)
+}
+
+pub fn read_bounty_pool(e: &Env) -> i128 {
+    e.storage().instance().bump(INSTANCE_BUMP_AMOUNT);
+    e.storage()
+        .instance()
+        .get(&DataKey::BountyPool)
+        .unwrap_or(0)
+}
+
+pub fn write_bounty_pool(e: &Env, amount: i128) {
+    e.storage().instance().bump(INSTANCE_BUMP_AMOUNT);
+    e.storage().instance().set(&DataKey::BountyPool, &amount);
+}
+
+// Protocol's cut of an amount at the given rate, as the registry computes it
+pub fn fee_amount(e: &Env, amount: i128, bps: u32) -> Result<i128, Error> {
+    registry_client(e)
+        .try_fee_amount(&amount, &bps)
+        .ok()
+        .and_then(|fee| fee.ok())
+        .ok_or(Error::ArithmeticOverflow)
+}
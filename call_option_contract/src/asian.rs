@@ -0,0 +1,101 @@
+use crate::errors::Error;
+use crate::option::{get_init_time, get_sources, median};
+use crate::oracle;
+use crate::storage_types::{AsianConfig, DataKey, OptionInfo, OptionKind, PERSISTENT_BUMP_AMOUNT};
+use soroban_sdk::{Env, Vec};
+
+pub fn read_fixing(e: &Env, index: u32) -> Option<i128> {
+    let key = DataKey::Fixing(index);
+    let price = e.storage().persistent().get::<DataKey, i128>(&key);
+    if price.is_some() {
+        e.storage().persistent().bump(&key, PERSISTENT_BUMP_AMOUNT);
+    }
+    price
+}
+
+fn write_fixing(e: &Env, index: u32, price: i128) {
+    let key = DataKey::Fixing(index);
+    e.storage().persistent().set(&key, &price);
+    e.storage().persistent().bump(&key, PERSISTENT_BUMP_AMOUNT);
+}
+
+pub fn get_asian(option: &OptionInfo) -> Result<&AsianConfig, Error> {
+    match &option.kind {
+        OptionKind::Asian(asian) => Ok(asian),
+        _ => Err(Error::NoFixings),
+    }
+}
+
+// Fixings must be scheduled in increasing order, no later than expiration
+pub fn check_asian_terms(option: &OptionInfo) -> Result<(), Error> {
+    let asian = match &option.kind {
+        OptionKind::Asian(asian) => asian,
+        _ => return Ok(()),
+    };
+    if asian.fixings.is_empty() {
+        return Err(Error::InvalidFixings);
+    }
+    let mut previous = None;
+    for fixing in asian.fixings.iter() {
+        if fixing > option.expiration_date
+            || matches!(previous, Some(previous) if fixing <= previous)
+        {
+            return Err(Error::InvalidFixings);
+        }
+        previous = Some(fixing);
+    }
+    Ok(())
+}
+
+// Timestamp of a scheduled fixing
+pub fn get_fixing_time(e: &Env, asian: &AsianConfig, index: u32) -> Result<u64, Error> {
+    let offset = asian.fixings.get(index).ok_or(Error::NoFixings)?;
+    offset
+        .checked_add(get_init_time(e)?)
+        .ok_or(Error::ArithmeticOverflow)
+}
+
+// Records the oracle price at a fixing: the latest price published at or
+// before the fixing time and no older than max_price_age then, or the median
+// of those prices over the configured sources.
+pub fn record_fixing_price(
+    e: &Env,
+    option: &OptionInfo,
+    asian: &AsianConfig,
+    index: u32,
+) -> Result<i128, Error> {
+    let time = get_fixing_time(e, asian, index)?;
+    if e.ledger().timestamp() < time {
+        return Err(Error::FixingNotDue);
+    }
+    if read_fixing(e, index).is_some() {
+        return Err(Error::FixingRecorded);
+    }
+    let oracle_client = oracle::Client::new(e, &option.oracle_contract_id);
+    let asset = oracle::Asset::Stellar(option.escrow_token.clone());
+    let start = time.saturating_sub(option.settlement.max_price_age);
+    let mut prices = Vec::<i128>::new(e);
+    for source in get_sources(e, option).iter() {
+        let price_data = oracle_client
+            .prices_by_source(&source, &asset, &start, &time)
+            .last()
+            .ok_or(Error::PriceUnavailable)?;
+        if price_data.price <= 0 {
+            return Err(Error::InvalidPrice);
+        }
+        prices.push_back(price_data.price);
+    }
+    let price = median(&prices).ok_or(Error::NoPriceSources)?;
+    write_fixing(e, index, price);
+    Ok(price)
+}
+
+// Arithmetic average of the fixings, rounded down, once all were recorded
+pub fn get_average_price(e: &Env, asian: &AsianConfig) -> Result<i128, Error> {
+    let mut sum: i128 = 0;
+    for index in 0..asian.fixings.len() {
+        let price = read_fixing(e, index).ok_or(Error::MissingFixings)?;
+        sum = sum.checked_add(price).ok_or(Error::ArithmeticOverflow)?;
+    }
+    Ok(sum / i128::from(asian.fixings.len()))
+}
@@ -0,0 +1,95 @@
+use crate::errors::Error;
+use crate::option::{compare_to_price, get_expiry, get_init_time, get_sources};
+use crate::oracle;
+use crate::storage_types::{
+    BarrierConfig, BarrierType, DataKey, OptionInfo, OptionKind, INSTANCE_BUMP_AMOUNT,
+};
+use core::cmp::Ordering;
+use soroban_sdk::Env;
+
+pub fn read_barrier_hit(e: &Env) -> bool {
+    e.storage().instance().bump(INSTANCE_BUMP_AMOUNT);
+    e.storage()
+        .instance()
+        .get(&DataKey::BarrierHit)
+        .unwrap_or(false)
+}
+
+pub fn write_barrier_hit(e: &Env) {
+    e.storage().instance().bump(INSTANCE_BUMP_AMOUNT);
+    e.storage().instance().set(&DataKey::BarrierHit, &true);
+}
+
+pub fn get_barrier(option: &OptionInfo) -> Result<&BarrierConfig, Error> {
+    match &option.kind {
+        OptionKind::Barrier(barrier) => Ok(barrier),
+        _ => Err(Error::NoBarrier),
+    }
+}
+
+// Whether an oracle price reaches the barrier level
+pub fn is_breach(option: &OptionInfo, barrier: &BarrierConfig, price: i128) -> Result<bool, Error> {
+    let ordering = compare_to_price(option, price, barrier.level)?;
+    Ok(match barrier.barrier_type {
+        BarrierType::UpAndOut | BarrierType::UpAndIn => ordering != Ordering::Less,
+        BarrierType::DownAndOut | BarrierType::DownAndIn => ordering != Ordering::Greater,
+    })
+}
+
+// Whether the barrier was reached between creation and expiration, either as
+// recorded or in the oracle history of any configured source.
+pub fn is_barrier_hit(
+    e: &Env,
+    option: &OptionInfo,
+    barrier: &BarrierConfig,
+) -> Result<bool, Error> {
+    if read_barrier_hit(e) {
+        return Ok(true);
+    }
+    let oracle_client = oracle::Client::new(e, &option.oracle_contract_id);
+    let asset = oracle::Asset::Stellar(option.escrow_token.clone());
+    let start = get_init_time(e)?;
+    let end = get_expiry(e, option)?;
+    for source in get_sources(e, option).iter() {
+        for price_data in oracle_client
+            .prices_by_source(&source, &asset, &start, &end)
+            .iter()
+        {
+            if price_data.price > 0 && is_breach(option, barrier, price_data.price)? {
+                return Ok(true);
+            }
+        }
+    }
+    Ok(false)
+}
+
+// Rejects settling a barrier option that was knocked out or never knocked in,
+// and records a breach found in the oracle history.
+pub fn check_barrier(e: &Env, option: &OptionInfo) -> Result<(), Error> {
+    let barrier = match &option.kind {
+        OptionKind::Barrier(barrier) => barrier,
+        _ => return Ok(()),
+    };
+    let hit = is_barrier_hit(e, option, barrier)?;
+    if hit && !read_barrier_hit(e) {
+        write_barrier_hit(e);
+    }
+    match barrier.barrier_type {
+        BarrierType::UpAndOut | BarrierType::DownAndOut if hit => Err(Error::KnockedOut),
+        BarrierType::UpAndIn | BarrierType::DownAndIn if !hit => Err(Error::NotKnockedIn),
+        _ => Ok(()),
+    }
+}
+
+// Checks the barrier terms against the oracle price at creation, which must
+// not have reached the level yet.
+pub fn check_barrier_terms(option: &OptionInfo, spot: i128) -> Result<(), Error> {
+    let barrier = match &option.kind {
+        OptionKind::Barrier(barrier) => barrier,
+        _ => return Ok(()),
+    };
+    if barrier.level <= 0 || is_breach(option, barrier, spot)? {
+        return Err(Error::InvalidBarrier);
+    }
+    Ok(())
+}
@@ -0,0 +1,88 @@
+use crate::fixed_point::{exp, ln, norm_cdf, norm_pdf, wad_div, wad_mul, wad_sqrt, WAD};
+use crate::storage_types::Greeks;
+
+// Black-Scholes inputs in 18-decimal fixed point. Prices are normalized by
+// the strike, so results are per unit of strike price.
+pub struct Inputs {
+    // Spot price divided by the strike price
+    pub moneyness: i128,
+    // Time to expiry in years
+    pub time: i128,
+    // Annualized continuously compounded risk-free rate
+    pub rate: i128,
+    // Annualized volatility
+    pub volatility: i128,
+}
+
+impl Inputs {
+    // Discount factor e^(-rT) applied to the strike
+    fn discount(&self) -> Option<i128> {
+        exp(-wad_mul(self.rate, self.time)?)
+    }
+
+    // volatility * sqrt(time)
+    fn vol_sqrt_time(&self) -> Option<i128> {
+        wad_mul(self.volatility, wad_sqrt(self.time)?)
+    }
+
+    // d1 and d2 of the Black-Scholes formula
+    fn d1_d2(&self) -> Option<(i128, i128)> {
+        let vol_sqrt_time = self.vol_sqrt_time()?;
+        let variance = wad_mul(self.volatility, self.volatility)?;
+        let drift = wad_mul(self.rate.checked_add(variance / 2)?, self.time)?;
+        let d1 = wad_div(ln(self.moneyness)?.checked_add(drift)?, vol_sqrt_time)?;
+        Some((d1, d1 - vol_sqrt_time))
+    }
+
+    // No time or no volatility left: the price is known, so the model
+    // degenerates to the discounted intrinsic value
+    fn is_degenerate(&self) -> bool {
+        self.time == 0 || self.volatility == 0
+    }
+}
+
+// Call price divided by the strike price
+pub fn call_price(inputs: &Inputs) -> Option<i128> {
+    let discount = inputs.discount()?;
+    if inputs.is_degenerate() {
+        return Some((inputs.moneyness - discount).max(0));
+    }
+    let (d1, d2) = inputs.d1_d2()?;
+    let price =
+        wad_mul(inputs.moneyness, norm_cdf(d1)?)?.checked_sub(wad_mul(discount, norm_cdf(d2)?)?)?;
+    Some(price.max(0))
+}
+
+// Greeks of the strike-normalized call price: delta is unchanged, gamma must
+// still be divided by the strike and vega, theta and rho multiplied by it.
+pub fn call_greeks(inputs: &Inputs) -> Option<Greeks> {
+    let discount = inputs.discount()?;
+    if inputs.is_degenerate() {
+        // Exercised for sure or not at all: only the discounting is sensitive
+        let exercised = if inputs.moneyness > discount { WAD } else { 0 };
+        let discounted = wad_mul(discount, exercised)?;
+        return Some(Greeks {
+            delta: exercised,
+            gamma: 0,
+            vega: 0,
+            theta: -wad_mul(inputs.rate, discounted)?,
+            rho: wad_mul(inputs.time, discounted)?,
+        });
+    }
+    let (d1, d2) = inputs.d1_d2()?;
+    let density = norm_pdf(d1)?;
+    let sqrt_time = wad_sqrt(inputs.time)?;
+    let vol_sqrt_time = inputs.vol_sqrt_time()?;
+    let discounted = wad_mul(discount, norm_cdf(d2)?)?;
+    let decay = wad_div(
+        wad_mul(wad_mul(inputs.moneyness, density)?, inputs.volatility)?,
+        2 * sqrt_time,
+    )?;
+    Some(Greeks {
+        delta: norm_cdf(d1)?,
+        gamma: wad_div(density, wad_mul(inputs.moneyness, vol_sqrt_time)?)?,
+        vega: wad_mul(wad_mul(inputs.moneyness, density)?, sqrt_time)?,
+        theta: -decay - wad_mul(inputs.rate, discounted)?,
+        rho: wad_mul(inputs.time, discounted)?,
+    })
+}
@@ -0,0 +1,95 @@
+use soroban_sdk::contracterror;
+
+#[contracterror]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, PartialOrd, Ord)]
+#[repr(u32)]
+pub enum Error {
+    // Amount arithmetic overflowed i128
+    ArithmeticOverflow = 1,
+    // Oracle has no price for the escrow token
+    PriceUnavailable = 2,
+    // Oracle price was observed before expiration
+    PriceBeforeExpiry = 3,
+    // Oracle price is older than the configured max price age
+    StalePrice = 4,
+    // Oracle price is zero or negative
+    InvalidPrice = 5,
+    // Fewer oracle prices than min_samples in the TWAP window
+    InsufficientSamples = 6,
+    // Median price source lists no oracle sources
+    NoPriceSources = 7,
+    // init_option was already called
+    AlreadyInitialized = 8,
+    // init_option was not called yet
+    NotInitialized = 9,
+    // Strike or escrow amount is not positive, or premium is negative
+    InvalidAmount = 10,
+    // Option has no buyer yet
+    NotPurchased = 11,
+    // Option already has a buyer
+    AlreadyPurchased = 12,
+    // Option has not reached its expiration date
+    NotExpired = 13,
+    // Exercise window after expiration is over
+    ExerciseWindowClosed = 14,
+    // Exercise window after expiration is still running
+    ExerciseWindowOpen = 15,
+    // Settlement price is on the non-paying side of the strike
+    OutOfTheMoney = 16,
+    // Option was already exercised, expired or cancelled
+    OptionClosed = 17,
+    // Position balance is lower than the amount moved or settled
+    InsufficientBalance = 18,
+    // Spender's position allowance is lower than the amount moved
+    InsufficientAllowance = 19,
+    // Allowance expires before the current ledger
+    InvalidExpirationLedger = 20,
+    // Address holds no position units to settle
+    NoPosition = 21,
+    // More units requested than are left for sale
+    InsufficientUnits = 22,
+    // Position units cannot be sent to the option contract itself
+    InvalidRecipient = 23,
+    // Quoted premiums must be paid in the underlying token
+    InvalidPremiumToken = 24,
+    // Premium is above the buyer's limit
+    PremiumAboveLimit = 25,
+    // Option registry has no admin and fees configured
+    NoAdmin = 27,
+    // Oracle was not approved by the admin
+    OracleNotAllowed = 28,
+    // Escrow and underlying token pair was not approved by the admin
+    PairNotAllowed = 29,
+    // Stored data was written by a newer contract version
+    InvalidVersion = 30,
+    // No position could be settled yet
+    NothingToSettle = 31,
+    // Option has no barrier
+    NoBarrier = 32,
+    // Barrier was reached, the option is worthless
+    KnockedOut = 33,
+    // Barrier was never reached, the option is worthless
+    NotKnockedIn = 34,
+    // Oracle observation does not reach the barrier during the option's life
+    BarrierNotBreached = 35,
+    // Barrier level is not positive or already reached at creation
+    InvalidBarrier = 36,
+    // Black-Scholes quotes only price vanilla options
+    QuoteUnsupported = 37,
+    // Option has no fixing with this index
+    NoFixings = 38,
+    // Fixings are missing, unordered or after expiration
+    InvalidFixings = 39,
+    // Fixing time has not come yet
+    FixingNotDue = 40,
+    // Fixing was already recorded
+    FixingRecorded = 41,
+    // Settlement needs every fixing recorded
+    MissingFixings = 42,
+    // Upper strike of a spread is not above the strike
+    InvalidSpread = 43,
+    // Option has reached its expiration date
+    Expired = 44,
+    // Minimum TWAP samples are set without a TWAP window
+    InvalidSettlement = 45,
+}
@@ -0,0 +1,174 @@
+use soroban_sdk::{symbol_short, Address, Env};
+
+// Lifecycle topics are ("option", <step>, <party the event concerns>), without
+// the party for events that concern the whole option.
+// Position token events follow the SEP-41 token layout.
+
+pub(crate) fn created(
+    e: &Env,
+    seller: &Address,
+    escrow_token: &Address,
+    escrow_amount: i128,
+    strike_price: i128,
+    expires_at: u64,
+) {
+    let topics = (
+        symbol_short!("option"),
+        symbol_short!("created"),
+        seller.clone(),
+    );
+    e.events().publish(
+        topics,
+        (
+            escrow_token.clone(),
+            escrow_amount,
+            strike_price,
+            expires_at,
+        ),
+    );
+}
+
+pub(crate) fn purchased(
+    e: &Env,
+    buyer: &Address,
+    premium_token: &Address,
+    premium: i128,
+    deposit_amount: i128,
+) {
+    let topics = (
+        symbol_short!("option"),
+        symbol_short!("purchased"),
+        buyer.clone(),
+    );
+    e.events()
+        .publish(topics, (premium_token.clone(), premium, deposit_amount));
+}
+
+// Holder received the payout, the escrow or a spread's payoff less the
+// protocol fee, and the seller the strike deposit
+pub(crate) fn exercised(
+    e: &Env,
+    holder: &Address,
+    price: i128,
+    payout_token: &Address,
+    payout: i128,
+    deposit_amount: i128,
+) {
+    let topics = (
+        symbol_short!("option"),
+        symbol_short!("exercised"),
+        holder.clone(),
+    );
+    e.events().publish(
+        topics,
+        (price, payout_token.clone(), payout, deposit_amount),
+    );
+}
+
+// Holder got the strike deposit back and the seller the collateral: the
+// escrow, or the underlying held for a spread or binary
+pub(crate) fn expired(
+    e: &Env,
+    holder: &Address,
+    deposit_amount: i128,
+    collateral_token: &Address,
+    collateral: i128,
+) {
+    let topics = (
+        symbol_short!("option"),
+        symbol_short!("expired"),
+        holder.clone(),
+    );
+    e.events().publish(
+        topics,
+        (deposit_amount, collateral_token.clone(), collateral),
+    );
+}
+
+// Holder burned units before settlement, with the same transfers as expired
+pub(crate) fn refunded(
+    e: &Env,
+    holder: &Address,
+    deposit_amount: i128,
+    collateral_token: &Address,
+    collateral: i128,
+) {
+    let topics = (
+        symbol_short!("option"),
+        symbol_short!("refunded"),
+        holder.clone(),
+    );
+    e.events().publish(
+        topics,
+        (deposit_amount, collateral_token.clone(), collateral),
+    );
+}
+
+// Seller took back the collateral of unsold units
+pub(crate) fn withdrawn(e: &Env, seller: &Address, collateral_token: &Address, collateral: i128) {
+    let topics = (
+        symbol_short!("option"),
+        symbol_short!("withdrawn"),
+        seller.clone(),
+    );
+    e.events()
+        .publish(topics, (collateral_token.clone(), collateral));
+}
+
+pub(crate) fn mint(e: &Env, to: &Address, amount: i128) {
+    let topics = (symbol_short!("mint"), to.clone());
+    e.events().publish(topics, amount);
+}
+
+pub(crate) fn transfer(e: &Env, from: &Address, to: &Address, amount: i128) {
+    let topics = (symbol_short!("transfer"), from.clone(), to.clone());
+    e.events().publish(topics, amount);
+}
+
+pub(crate) fn approve(
+    e: &Env,
+    from: &Address,
+    spender: &Address,
+    amount: i128,
+    expiration_ledger: u32,
+) {
+    let topics = (symbol_short!("approve"), from.clone(), spender.clone());
+    e.events().publish(topics, (amount, expiration_ledger));
+}
+
+pub(crate) fn burn(e: &Env, from: &Address, amount: i128) {
+    let topics = (symbol_short!("burn"), from.clone());
+    e.events().publish(topics, amount);
+}
+
+// Treasury received a protocol fee
+pub(crate) fn fee(e: &Env, treasury: &Address, token: &Address, amount: i128) {
+    let topics = (
+        symbol_short!("option"),
+        symbol_short!("fee"),
+        treasury.clone(),
+    );
+    e.events().publish(topics, (token.clone(), amount));
+}
+
+// Keeper settled positions of an expired option and earned a bounty
+pub(crate) fn settled(e: &Env, keeper: &Address, positions: u32, bounty: i128) {
+    let topics = (
+        symbol_short!("option"),
+        symbol_short!("settled"),
+        keeper.clone(),
+    );
+    e.events().publish(topics, (positions, bounty));
+}
+
+// Oracle price `source` published at `timestamp` reached the barrier
+pub(crate) fn barrier(e: &Env, source: u32, timestamp: u64, price: i128) {
+    let topics = (symbol_short!("option"), symbol_short!("barrier"));
+    e.events().publish(topics, (source, timestamp, price));
+}
+
+// Oracle price recorded at a fixing of an Asian option
+pub(crate) fn fixing(e: &Env, index: u32, price: i128) {
+    let topics = (symbol_short!("option"), symbol_short!("fixing"));
+    e.events().publish(topics, (index, price));
+}
@@ -0,0 +1,166 @@
+// Strike prices are fixed-point numbers with this many decimals:
+// a strike of 1.5 underlying tokens per escrow token is 15_000_000.
+pub(crate) const STRIKE_DECIMALS: u32 = 7;
+
+pub fn pow10(exp: u32) -> Option<i128> {
+    10_i128.checked_pow(exp)
+}
+
+// Converts a fixed-point value between two decimal scales, truncating when
+// the target scale is smaller. Returns None on overflow.
+pub fn rescale(value: i128, from_decimals: u32, to_decimals: u32) -> Option<i128> {
+    if to_decimals >= from_decimals {
+        value.checked_mul(pow10(to_decimals - from_decimals)?)
+    } else {
+        value.checked_div(pow10(from_decimals - to_decimals)?)
+    }
+}
+
+// The functions below work on 18-decimal fixed-point numbers and return None
+// on overflow or outside their domain.
+pub(crate) const WAD_DECIMALS: u32 = 18;
+pub(crate) const WAD: i128 = 1_000_000_000_000_000_000;
+
+const LN_2: i128 = 693_147_180_559_945_309;
+const SQRT_2PI: i128 = 2_506_628_274_631_000_502;
+
+// a * b / WAD, split so the intermediate product stays small
+pub fn wad_mul(a: i128, b: i128) -> Option<i128> {
+    (a / WAD)
+        .checked_mul(b)?
+        .checked_add((a % WAD).checked_mul(b)? / WAD)
+}
+
+pub fn wad_div(a: i128, b: i128) -> Option<i128> {
+    a.checked_mul(WAD)?.checked_div(b)
+}
+
+pub fn wad_sqrt(x: i128) -> Option<i128> {
+    if x < 0 {
+        return None;
+    }
+    // Newton's method on the integer square root of x * WAD
+    let n = x.checked_mul(WAD)?;
+    if n < 2 {
+        return Some(n);
+    }
+    let mut root = n;
+    let mut next = n / 2 + 1;
+    while next < root {
+        root = next;
+        next = (root + n / root) / 2;
+    }
+    Some(root)
+}
+
+// Natural logarithm of x > 0
+pub fn ln(x: i128) -> Option<i128> {
+    if x <= 0 {
+        return None;
+    }
+    // x = m * 2^k with m in [1, 2)
+    let mut m = x;
+    let mut k: i128 = 0;
+    while m >= 2 * WAD {
+        m /= 2;
+        k += 1;
+    }
+    while m < WAD {
+        m *= 2;
+        k -= 1;
+    }
+    // ln(m) = 2 * atanh(z) = 2 * (z + z^3/3 + z^5/5 + ...), z = (m-1)/(m+1) < 1/3
+    let z = wad_div(m - WAD, m + WAD)?;
+    let z2 = wad_mul(z, z)?;
+    let mut term = z;
+    let mut sum = 0;
+    let mut n = 1;
+    while term != 0 {
+        sum += term / n;
+        term = wad_mul(term, z2)?;
+        n += 2;
+    }
+    k.checked_mul(LN_2)?.checked_add(2 * sum)
+}
+
+// e^x
+pub fn exp(x: i128) -> Option<i128> {
+    // e^x = 2^k * e^r with |r| <= ln(2) / 2
+    let half = if x < 0 { -LN_2 / 2 } else { LN_2 / 2 };
+    let k = x.checked_add(half)? / LN_2;
+    let r = x - k * LN_2;
+    let mut term = WAD;
+    let mut sum = WAD;
+    let mut n = 1;
+    while term != 0 {
+        term = wad_mul(term, r)? / n;
+        sum += term;
+        n += 1;
+    }
+    if k >= 0 {
+        sum.checked_mul(2_i128.checked_pow(u32::try_from(k).ok()?)?)
+    } else {
+        match 2_i128.checked_pow(u32::try_from(-k).ok()?) {
+            Some(divisor) => Some(sum / divisor),
+            None => Some(0),
+        }
+    }
+}
+
+// Standard normal density
+pub fn norm_pdf(x: i128) -> Option<i128> {
+    wad_div(exp(-wad_mul(x, x)? / 2)?, SQRT_2PI)
+}
+
+// Standard normal cumulative distribution, after Hart's double precision
+// algorithm as given in G. West, "Better approximations to cumulative normal
+// functions" (2005). The tail is computed directly, so there is no
+// cancellation far from 0.
+pub fn norm_cdf(x: i128) -> Option<i128> {
+    // Coefficients with 15 decimals: only their ratio is used, and the
+    // smaller scale keeps the polynomials within i128
+    const NUMERATOR: [i128; 7] = [
+        35_262_496_599_891,
+        700_383_064_443_688,
+        6_373_962_203_531_650,
+        33_912_866_078_383_000,
+        112_079_291_497_871_000,
+        221_213_596_169_931_000,
+        220_206_867_912_376_000,
+    ];
+    const DENOMINATOR: [i128; 8] = [
+        88_388_347_648_318,
+        1_755_667_163_182_640,
+        16_064_177_579_207_000,
+        86_780_732_202_946_100,
+        296_564_248_779_674_000,
+        637_333_633_378_831_000,
+        793_826_512_519_948_000,
+        440_413_735_824_752_000,
+    ];
+    let a = x.checked_abs()?;
+    let tail = if a > 37 * WAD {
+        0
+    } else {
+        let density = exp(-wad_mul(a, a)? / 2)?;
+        if a < 7_071_067_811_865_470_000 {
+            let mut numerator = 0;
+            for c in NUMERATOR {
+                numerator = wad_mul(numerator, a)? + c;
+            }
+            let mut denominator = 0;
+            for c in DENOMINATOR {
+                denominator = wad_mul(denominator, a)? + c;
+            }
+            wad_mul(density, wad_div(numerator, denominator)?)?
+        } else {
+            // Continued fraction for the far tail
+            let mut b = a + 650_000_000_000_000_000;
+            for k in [4, 3, 2, 1] {
+                b = a + wad_div(k * WAD, b)?;
+            }
+            wad_div(wad_div(density, b)?, SQRT_2PI)?
+        }
+    };
+    Some(if x > 0 { WAD - tail } else { tail })
+}
@@ -2,154 +2,903 @@
 
 #![no_std]
 
+mod admin;
+mod asian;
+mod barrier;
+mod black_scholes;
+mod errors;
+mod events;
+mod fixed_point;
 mod option;
+mod position;
+mod spread;
 mod storage_types;
+mod upgrade;
 mod oracle {
     soroban_sdk::contractimport!(
         file = "../oracle_soroban/target/wasm32-unknown-unknown/release/oracle.wasm"
     );
 }
+mod registry {
+    soroban_sdk::contractimport!(
+        file = "../option_registry/target/wasm32-unknown-unknown/release/option_registry.wasm"
+    );
+}
 
+use crate::admin::{
+    fee_amount, read_admin, read_bounty_pool, read_fees, read_oracle_allowed, read_pair_allowed,
+    write_bounty_pool,
+};
+use crate::asian::{check_asian_terms, get_asian, read_fixing, record_fixing_price};
+use crate::barrier::{
+    check_barrier, check_barrier_terms, get_barrier, is_barrier_hit, is_breach, read_barrier_hit,
+    write_barrier_hit,
+};
+use crate::errors::Error;
 use crate::option::{
-    check_time_bound, deposite_escrow, get_buyer, is_buyer_entered, is_initialized, load_option,
-    save_option, set_buyer, set_init_time,
+    add_bought, check_time_bound, check_transition, deposite_escrow, fix_settlement_price,
+    get_bought, get_deposit_amount, get_expiry, get_greeks, get_init_time, get_intrinsic_value,
+    get_premium, get_quoted_premium, get_settlement_price, get_sources, get_spot_price, get_state,
+    get_strike_value, is_exercise_window_closed, is_in_the_money, load_option, pro_rata,
+    read_settlement_price, save_option, set_init_time, take_collateral, transition,
+};
+use crate::position::{
+    close_position, open_position, read_allowance, read_balance, read_holder, read_holder_count,
+    read_outstanding, read_unsold, receive_balance, spend_allowance, spend_balance,
+    write_allowance, write_unsold,
 };
-use crate::storage_types::{DataKey, OptionInfo};
-use soroban_sdk::{contract, contractimpl, token, unwrap::UnwrapOptimized, Address, Env, log};
+use crate::spread::{check_spread_terms, get_spread_payout};
+use crate::storage_types::{
+    Greeks, OptionInfo, OptionKind, OptionState, Premium, PriceSource, QuoteConfig,
+    SettlementConfig, StrikePayment, TokenPair, STORAGE_VERSION,
+};
+use crate::upgrade::{migrate_storage, read_version, write_version};
+use soroban_sdk::{contract, contractimpl, token, Address, BytesN, Env, String, Vec};
 
 #[contract]
 pub struct EuropeanCallOption;
 
 #[contractimpl]
 impl EuropeanCallOption {
+    /// Replaces the contract code with the uploaded WASM `new_wasm_hash`,
+    /// keeping the contract id, the option and its positions. Call `migrate`
+    /// afterwards so the new code can convert data written by the old one.
+    /// Only the registry admin can upgrade, never the seller who deployed
+    /// the contract.
+    pub fn upgrade(e: Env, new_wasm_hash: BytesN<32>) -> Result<(), Error> {
+        read_admin(&e)?.require_auth();
+        e.deployer().update_current_contract_wasm(new_wasm_hash);
+        Ok(())
+    }
+
+    /// Converts the stored data to the layout of the running code. Only the
+    /// registry admin can migrate.
+    pub fn migrate(e: Env) -> Result<(), Error> {
+        read_admin(&e)?.require_auth();
+        migrate_storage(&e)
+    }
+
+    pub fn version(e: Env) -> u32 {
+        read_version(&e)
+    }
+
+    /// Writes the option. The oracle and the token pair must be allow-listed
+    /// in the option registry and the oracle must already have a price for the escrow token on every
+    /// configured source.
     pub fn init_option(
         e: Env,
         seller: Address,
-        strike_price: u32,
+        strike_price: i128,
         expiration_date: u64,
-        premium: u32,
-        escrow_token: Address,
-        escrow_amount: u32,
-        underlying_token: Address,
+        settlement: SettlementConfig,
+        kind: OptionKind,
+        premium: Premium,
+        premium_token: Address,
+        tokens: TokenPair,
+        escrow_amount: i128,
         oracle_contract_id: Address,
-    ) {
-        if is_initialized(&e) {
-            panic!("Option was already initialized");
+    ) -> Result<(), Error> {
+        let TokenPair {
+            escrow_token,
+            underlying_token,
+        } = tokens;
+        transition(&e, OptionState::Open)?;
+        // Fees are read from the registry on every purchase and exercise
+        read_admin(&e)?;
+        if !read_oracle_allowed(&e, &oracle_contract_id) {
+            return Err(Error::OracleNotAllowed);
+        }
+        if !read_pair_allowed(&e, &escrow_token, &underlying_token) {
+            return Err(Error::PairNotAllowed);
+        }
+        if strike_price <= 0 || escrow_amount <= 0 {
+            return Err(Error::InvalidAmount);
+        }
+        match &premium {
+            Premium::Fixed(premium) if *premium < 0 => return Err(Error::InvalidAmount),
+            Premium::Quoted(quote) if quote.volatility < 0 => return Err(Error::InvalidAmount),
+            Premium::Quoted(_) if premium_token != underlying_token => {
+                return Err(Error::InvalidPremiumToken)
+            }
+            Premium::Quoted(_) if kind != OptionKind::Vanilla => {
+                return Err(Error::QuoteUnsupported)
+            }
+            _ => {}
         }
-        if strike_price == 0 || escrow_amount == 0 {
-            panic!("Zero value is not allowed");
+        if let PriceSource::Median(sources) = &settlement.price_source {
+            if sources.is_empty() {
+                return Err(Error::NoPriceSources);
+            }
+        }
+        if settlement.min_samples > 0 && settlement.twap_window == 0 {
+            return Err(Error::InvalidSettlement);
+        }
+        let oracle_decimals = oracle::Client::new(&e, &oracle_contract_id).decimals();
+        let escrow_decimals = token::Client::new(&e, &escrow_token).decimals();
+        let underlying_decimals = token::Client::new(&e, &underlying_token).decimals();
+        let option = OptionInfo {
+            seller,
+            escrow_token,
+            escrow_amount,
+            underlying_token,
+            strike_price,
+            expiration_date,
+            settlement,
+            kind,
+            premium,
+            premium_token,
+            oracle_contract_id,
+            oracle_decimals,
+            escrow_decimals,
+            underlying_decimals,
+        };
+        if get_strike_value(&option)? == 0 && !matches!(option.kind, OptionKind::Spread(_)) {
+            return Err(Error::InvalidAmount);
         }
-        save_option(
+        // Refuse an oracle that does not quote the escrow token
+        let spot = get_spot_price(&e, &option)?;
+        check_barrier_terms(&option, spot)?;
+        check_asian_terms(&option)?;
+        check_spread_terms(&option)?;
+        save_option(&e, &option);
+        deposite_escrow(&e, &option)?;
+        write_unsold(&e, option.escrow_amount);
+        set_init_time(&e, &e.ledger().timestamp());
+        write_version(&e, STORAGE_VERSION);
+
+        events::created(
             &e,
-            &OptionInfo {
-                seller,
-                escrow_token,
-                escrow_amount,
-                underlying_token,
-                strike_price,
-                expiration_date,
-                premium,
-                oracle_contract_id,
-            },
+            &option.seller,
+            &option.escrow_token,
+            option.escrow_amount,
+            option.strike_price,
+            get_expiry(&e, &option)?,
         );
-        deposite_escrow(&e);
-        set_init_time(&e, &e.ledger().timestamp());
+        Ok(())
     }
-    pub fn buy_option(e: Env, buyer: Address) {
-        if !is_initialized(&e) {
-            panic!("Option was not initialized");
+    /// Buys `quantity` units of the escrow. The premium and the strike
+    /// deposit are charged pro rata, so a series can be sold in parts to any
+    /// number of buyers until no units are left. The purchase fails if the
+    /// premium, fixed or quoted at the current oracle price, is above
+    /// `max_premium`.
+    pub fn buy_option(
+        e: Env,
+        buyer: Address,
+        quantity: i128,
+        max_premium: i128,
+    ) -> Result<(), Error> {
+        let option = load_option(&e)?;
+        // Later buyers take units from a series that is already trading
+        if get_state(&e)? != OptionState::Purchased {
+            transition(&e, OptionState::Purchased)?;
+        }
+        if check_time_bound(&e, &option)? {
+            return Err(Error::Expired);
+        }
+        if quantity <= 0 {
+            return Err(Error::InvalidAmount);
+        }
+        let unsold = read_unsold(&e);
+        if quantity > unsold {
+            return Err(Error::InsufficientUnits);
         }
-        let option = load_option(&e);
 
-        let premium_token_client = token::Client::new(&e, &option.escrow_token);
+        let premium_token_client = token::Client::new(&e, &option.premium_token);
         let underlying_token_client = token::Client::new(&e, &option.underlying_token);
         let contract = e.current_contract_address();
 
         buyer.require_auth();
 
-        // Send premium to the seller
-        premium_token_client.transfer(&buyer, &option.seller, &(option.premium as i128));
+        // Send the premium for these units to the seller, less the protocol fee
+        let premium = pro_rata(&option, get_premium(&e, &option)?, quantity)?;
+        if premium > max_premium {
+            return Err(Error::PremiumAboveLimit);
+        }
+        let fees = read_fees(&e)?;
+        let fee = fee_amount(&e, premium, fees.premium_bps)?;
+        premium_token_client.transfer(&buyer, &option.seller, &(premium - fee));
+
+        // The bounty_bps share of the fee funds this option's keeper bounties
+        let bounty = fee_amount(&e, fee, fees.bounty_bps)?;
+        if bounty > 0 {
+            premium_token_client.transfer(&buyer, &contract, &bounty);
+            let pool = read_bounty_pool(&e)
+                .checked_add(bounty)
+                .ok_or(Error::ArithmeticOverflow)?;
+            write_bounty_pool(&e, pool);
+        }
+        if fee > bounty {
+            premium_token_client.transfer(&buyer, &fees.treasury, &(fee - bounty));
+            events::fee(&e, &fees.treasury, &option.premium_token, fee - bounty);
+        }
+
+        // Depositing quantity * strikePrice, unless it is paid at exercise
+        let deposit_amount = match option.settlement.strike_payment {
+            StrikePayment::AtPurchase => pro_rata(&option, get_deposit_amount(&option)?, quantity)?,
+            StrikePayment::AtExercise => 0,
+        };
 
-        log!(&e, "option.premium {}");
-        // Depositing escrowAmount * strikePrice
-        let deposit_amount = option
-            .escrow_amount
-            .checked_mul(option.strike_price)
-            .unwrap_optimized() as i128;
+        if deposit_amount > 0 {
+            underlying_token_client.transfer(&buyer, &contract, &deposit_amount);
+        }
 
-        underlying_token_client.transfer(&buyer, &contract, &deposit_amount);
+        // Record the purchase and mint the buyer their position
+        write_unsold(&e, unsold - quantity);
+        add_bought(&e, &buyer, quantity)?;
+        open_position(&e, &buyer, quantity, deposit_amount)?;
 
-        // Set buyer of this option
-        set_buyer(&e, &buyer);
+        events::mint(&e, &buyer, quantity);
+        events::purchased(&e, &buyer, &option.premium_token, premium, deposit_amount);
+        Ok(())
     }
 
-    pub fn exercise_option(e: Env) {
-        if !check_time_bound(&e) {
-            panic!("Expiration Date is not fulfilled");
+    /// Holder exercises their position during the exercise window after
+    /// expiration. Only the holder signs: the seller's share of the strike
+    /// deposit and the holder's escrow are released by the contract without
+    /// the seller's cooperation. With `StrikePayment::AtExercise` the holder
+    /// pays their share of the strike amount to the seller here instead.
+    pub fn exercise_option(e: Env, holder: Address) -> Result<(), Error> {
+        let option = load_option(&e)?;
+        check_transition(&e, OptionState::Exercised)?;
+        if !check_time_bound(&e, &option)? {
+            return Err(Error::NotExpired);
+        }
+        if is_exercise_window_closed(&e, &option)? {
+            return Err(Error::ExerciseWindowClosed);
+        }
+
+        holder.require_auth();
+
+        let units = read_balance(&e, &holder);
+        if units == 0 {
+            return Err(Error::NoPosition);
         }
+        let price = fix_settlement_price(&e, &option)?;
+        if !is_in_the_money(&option, price)? {
+            return Err(Error::OutOfTheMoney);
+        }
+        check_barrier(&e, &option)?;
+        exercise_position(&e, &option, &holder, units, price)
+    }
 
-        if !is_initialized(&e) || !is_buyer_entered(&e) {
-            panic!("Option has not been initialized");
+    /// Fixes the settlement price from the oracle once the option has
+    /// expired and returns it. Anyone can call it; exercise_option and settle
+    /// fix it as well.
+    pub fn fix_price(e: Env) -> Result<i128, Error> {
+        let option = load_option(&e)?;
+        if !check_time_bound(&e, &option)? {
+            return Err(Error::NotExpired);
         }
-        let option = load_option(&e);
-        let contract = e.current_contract_address();
-        let underlying_token_client = token::Client::new(&e, &option.underlying_token);
-        let escrow_token_client = token::Client::new(&e, &option.escrow_token);
-        let deposited_amount = option
-            .escrow_amount
-            .checked_mul(option.strike_price)
-            .unwrap_optimized() as i128;
-        let buyer = get_buyer(&e);
-
-        let oracle_client = oracle::Client::new(&e, &option.oracle_contract_id);
-        let asset = oracle::Asset::Stellar(escrow_token_client.address.clone());
-        let current_price = oracle_client.lastprice(&asset);
-
-        if let Some(price_data) = current_price {
-            if price_data.price < option.strike_price as i128 {
-                // Send strikePrice * escrowAmount to buyer
-                buyer.require_auth();
-                underlying_token_client.transfer(&contract, &buyer, &deposited_amount);
-
-                // Send escrowAmount to seller
-                escrow_token_client.transfer(
-                    &contract,
-                    &option.seller,
-                    &(option.escrow_amount as i128),
-                );
-            } else {
-                option.seller.require_auth();
-                // Send strikePrice * escrowAmount to seller
-                underlying_token_client.transfer(&contract, &option.seller, &deposited_amount);
+        fix_settlement_price(&e, &option)
+    }
 
-                // Send escrowAmount to buyer
-                escrow_token_client.transfer(&contract, &buyer, &(option.escrow_amount as i128));
+    /// Settlement price, once fixed.
+    pub fn settlement_price(e: Env) -> Option<i128> {
+        read_settlement_price(&e)
+    }
+
+    /// Records that the barrier was reached, proven by the oracle price
+    /// `source` published at `timestamp`, between creation and expiration.
+    /// Anyone can call it.
+    pub fn trigger_barrier(e: Env, source: u32, timestamp: u64) -> Result<(), Error> {
+        let option = load_option(&e)?;
+        let barrier = get_barrier(&option)?;
+        match get_state(&e)? {
+            OptionState::Open | OptionState::Purchased => {}
+            _ => return Err(Error::OptionClosed),
+        }
+        if read_barrier_hit(&e) {
+            return Ok(());
+        }
+        if !get_sources(&e, &option).contains(source)
+            || timestamp < get_init_time(&e)?
+            || timestamp > get_expiry(&e, &option)?
+        {
+            return Err(Error::BarrierNotBreached);
+        }
+        let asset = oracle::Asset::Stellar(option.escrow_token.clone());
+        let prices = oracle::Client::new(&e, &option.oracle_contract_id)
+            .prices_by_source(&source, &asset, &timestamp, &timestamp);
+        for price_data in prices.iter() {
+            if price_data.price > 0 && is_breach(&option, barrier, price_data.price)? {
+                write_barrier_hit(&e);
+                events::barrier(&e, source, timestamp, price_data.price);
+                return Ok(());
             }
-            e.storage().instance().remove(&DataKey::OptionInfo);
-            e.storage().instance().remove(&DataKey::Buyer);
-        } else {
-            panic!("Failed to fetch price from Oracle");
         }
+        Err(Error::BarrierNotBreached)
+    }
+
+    /// Whether the barrier was reached so far, as recorded or as found in the
+    /// oracle history.
+    pub fn barrier_hit(e: Env) -> Result<bool, Error> {
+        let option = load_option(&e)?;
+        is_barrier_hit(&e, &option, get_barrier(&option)?)
+    }
+
+    /// Records the oracle price at fixing `index` of an Asian option, once its
+    /// time has come. Anyone can call it; every fixing must be recorded
+    /// before the option settles on their average.
+    pub fn record_fixing(e: Env, index: u32) -> Result<i128, Error> {
+        let option = load_option(&e)?;
+        let asian = get_asian(&option)?;
+        let price = record_fixing_price(&e, &option, asian, index)?;
+        events::fixing(&e, index, price);
+        Ok(price)
+    }
+
+    /// Oracle price recorded at fixing `index`, if any.
+    pub fn fixing(e: Env, index: u32) -> Option<i128> {
+        read_fixing(&e, index)
+    }
+
+    /// Settles a position that was not exercised once the exercise window has
+    /// closed. Anyone can call it: the holder gets their share of the strike
+    /// deposit back and the seller gets the matching escrow back.
+    pub fn expire(e: Env, holder: Address) -> Result<(), Error> {
+        let option = load_option(&e)?;
+        check_transition(&e, OptionState::Expired)?;
+        if !is_exercise_window_closed(&e, &option)? {
+            return Err(Error::ExerciseWindowOpen);
+        }
+        let units = read_balance(&e, &holder);
+        if units == 0 {
+            return Err(Error::NoPosition);
+        }
+        expire_position(&e, &option, &holder, units)
+    }
+
+    /// Settles the positions of up to `limit` holders from index `start` that
+    /// can be settled without their holder: in the money positions whose
+    /// strike was deposited at purchase are exercised for their holder during
+    /// the exercise window, and all remaining positions are expired once it
+    /// has closed, or right away when a barrier made them worthless. Settled holders leave the list, so keepers page through it
+    /// from 0 until nothing is left to settle. Anyone can call it; `keeper` is
+    /// paid the `keeper_bounty` from the bounty pool, or whatever is left of
+    /// it. Returns the number of positions settled.
+    pub fn settle(e: Env, keeper: Address, start: u32, limit: u32) -> Result<u32, Error> {
+        let settlement = check_settlement(&e, start, limit)?;
+        settle_positions(&e, &keeper, settlement)
     }
 
-    pub fn withdraw(e: Env) {
-        let option = load_option(&e);
-        if is_initialized(&e) && is_buyer_entered(&e) {
-            panic!("Seller can't withdraw funds");
+    /// Calls `settle` on each option contract in `options` for up to `limit`
+    /// holders from the first, and returns how many of them settled
+    /// positions. Options with nothing to settle are skipped, so keepers can
+    /// pass every option they track.
+    pub fn settle_expired(
+        e: Env,
+        keeper: Address,
+        options: Vec<Address>,
+        limit: u32,
+    ) -> Result<u32, Error> {
+        let mut settled = 0;
+        for option in options.iter() {
+            // A contract cannot call itself, so this option settles locally.
+            // Nothing is rolled back here, so once its checks passed any
+            // error fails the whole call.
+            let result = if option == e.current_contract_address() {
+                match check_settlement(&e, 0, limit) {
+                    Ok(settlement) => {
+                        settle_positions(&e, &keeper, settlement)?;
+                        true
+                    }
+                    Err(_) => false,
+                }
+            } else {
+                matches!(
+                    EuropeanCallOptionClient::new(&e, &option).try_settle(&keeper, &0, &limit),
+                    Ok(Ok(_))
+                )
+            };
+            if result {
+                settled += 1;
+            }
         }
+        Ok(settled)
+    }
 
-        if !is_initialized(&e) {
-            panic!("Option wasn't initialized yet");
+    /// Adds premium tokens to the pool keeper bounties are paid from, until
+    /// the option is closed.
+    pub fn fund_bounty_pool(e: Env, from: Address, amount: i128) -> Result<(), Error> {
+        let option = load_option(&e)?;
+        match get_state(&e)? {
+            OptionState::Open | OptionState::Purchased => {}
+            _ => return Err(Error::OptionClosed),
+        }
+        if amount <= 0 {
+            return Err(Error::InvalidAmount);
+        }
+        from.require_auth();
+        token::Client::new(&e, &option.premium_token).transfer(
+            &from,
+            &e.current_contract_address(),
+            &amount,
+        );
+        let pool = read_bounty_pool(&e)
+            .checked_add(amount)
+            .ok_or(Error::ArithmeticOverflow)?;
+        write_bounty_pool(&e, pool);
+        Ok(())
+    }
+
+    pub fn bounty_pool(e: Env) -> i128 {
+        read_bounty_pool(&e)
+    }
+
+    /// Number of addresses holding position units, the holders `settle`
+    /// pages through.
+    pub fn holder_count(e: Env) -> u32 {
+        read_holder_count(&e)
+    }
+
+    /// Seller takes back the escrow for units nobody bought. Before the
+    /// first sale this cancels the option; afterwards sold positions keep
+    /// trading and settle as usual.
+    pub fn withdraw(e: Env) -> Result<(), Error> {
+        let option = load_option(&e)?;
+        let state = get_state(&e)?;
+        if state == OptionState::Open {
+            transition(&e, OptionState::Cancelled)?;
+        } else {
+            check_transition(&e, OptionState::Expired)?;
+        }
+        let unsold = read_unsold(&e);
+        if unsold == 0 {
+            return Err(Error::InsufficientUnits);
         }
 
         option.seller.require_auth();
-        token::Client::new(&e, &option.escrow_token).transfer(
+        let (collateral_token, collateral) = take_collateral(&e, &option, unsold)?;
+        token::Client::new(&e, &collateral_token).transfer(
+            &e.current_contract_address(),
+            &option.seller,
+            &collateral,
+        );
+        write_unsold(&e, 0);
+        if state == OptionState::Purchased {
+            close_if_settled(&e, &option, OptionState::Expired)?;
+        } else {
+            return_bounty_pool(&e, &option);
+        }
+
+        events::withdrawn(&e, &option.seller, &collateral_token, collateral);
+        Ok(())
+    }
+
+    // Long position token, SEP-41 compatible. One unit is the right to buy one
+    // escrow token unit at the strike; units are backed by the strike deposit.
+
+    pub fn allowance(e: Env, from: Address, spender: Address) -> i128 {
+        read_allowance(&e, &from, &spender).amount
+    }
+
+    pub fn approve(
+        e: Env,
+        from: Address,
+        spender: Address,
+        amount: i128,
+        expiration_ledger: u32,
+    ) -> Result<(), Error> {
+        from.require_auth();
+        check_nonnegative_amount(amount)?;
+        write_allowance(&e, &from, &spender, amount, expiration_ledger)?;
+        events::approve(&e, &from, &spender, amount, expiration_ledger);
+        Ok(())
+    }
+
+    pub fn balance(e: Env, id: Address) -> i128 {
+        read_balance(&e, &id)
+    }
+
+    pub fn transfer(e: Env, from: Address, to: Address, amount: i128) -> Result<(), Error> {
+        from.require_auth();
+        check_nonnegative_amount(amount)?;
+        check_recipient(&e, &to)?;
+        spend_balance(&e, &from, amount)?;
+        receive_balance(&e, &to, amount)?;
+        events::transfer(&e, &from, &to, amount);
+        Ok(())
+    }
+
+    pub fn transfer_from(
+        e: Env,
+        spender: Address,
+        from: Address,
+        to: Address,
+        amount: i128,
+    ) -> Result<(), Error> {
+        spender.require_auth();
+        check_nonnegative_amount(amount)?;
+        check_recipient(&e, &to)?;
+        spend_allowance(&e, &from, &spender, amount)?;
+        spend_balance(&e, &from, amount)?;
+        receive_balance(&e, &to, amount)?;
+        events::transfer(&e, &from, &to, amount);
+        Ok(())
+    }
+
+    /// Gives up position units before settlement: the holder gets their
+    /// share of the strike deposit back and the seller the matching escrow.
+    /// This is the refund path for a purchase that is no longer wanted.
+    pub fn burn(e: Env, from: Address, amount: i128) -> Result<(), Error> {
+        from.require_auth();
+        check_nonnegative_amount(amount)?;
+        let option = load_option(&e)?;
+        check_transition(&e, OptionState::Expired)?;
+        let (deposit_share, collateral_token, collateral) =
+            release_position(&e, &option, &from, amount)?;
+        events::refunded(&e, &from, deposit_share, &collateral_token, collateral);
+        Ok(())
+    }
+
+    pub fn burn_from(e: Env, spender: Address, from: Address, amount: i128) -> Result<(), Error> {
+        spender.require_auth();
+        check_nonnegative_amount(amount)?;
+        let option = load_option(&e)?;
+        check_transition(&e, OptionState::Expired)?;
+        spend_allowance(&e, &from, &spender, amount)?;
+        let (deposit_share, collateral_token, collateral) =
+            release_position(&e, &option, &from, amount)?;
+        events::refunded(&e, &from, deposit_share, &collateral_token, collateral);
+        Ok(())
+    }
+
+    pub fn decimals(e: Env) -> Result<u32, Error> {
+        Ok(load_option(&e)?.escrow_decimals)
+    }
+
+    pub fn name(e: Env) -> String {
+        String::from_slice(&e, "European Call Option")
+    }
+
+    pub fn symbol(e: Env) -> String {
+        String::from_slice(&e, "CALL")
+    }
+
+    pub fn get_option(e: Env) -> Result<OptionInfo, Error> {
+        load_option(&e)
+    }
+
+    /// Units bought by a buyer over all their purchases.
+    pub fn units_bought(e: Env, buyer: Address) -> i128 {
+        get_bought(&e, &buyer)
+    }
+
+    /// Units of the escrow still for sale.
+    pub fn units_available(e: Env) -> i128 {
+        read_unsold(&e)
+    }
+
+    pub fn state(e: Env) -> Result<OptionState, Error> {
+        get_state(&e)
+    }
+
+    pub fn init_time(e: Env) -> Result<u64, Error> {
+        get_init_time(&e)
+    }
+
+    pub fn expires_at(e: Env) -> Result<u64, Error> {
+        get_expiry(&e, &load_option(&e)?)
+    }
+
+    /// Seconds left until expiration, 0 once expired.
+    pub fn time_to_expiry(e: Env) -> Result<u64, Error> {
+        let expiry = get_expiry(&e, &load_option(&e)?)?;
+        Ok(expiry.saturating_sub(e.ledger().timestamp()))
+    }
+
+    /// Whether exercise_option would succeed now: the option is purchased,
+    /// inside its exercise window, not knocked out or still to be knocked in,
+    /// and the settlement price is in the money.
+    pub fn is_exercisable(e: Env) -> Result<bool, Error> {
+        let option = load_option(&e)?;
+        if get_state(&e)? != OptionState::Purchased
+            || !check_time_bound(&e, &option)?
+            || is_exercise_window_closed(&e, &option)?
+            || check_barrier(&e, &option).is_err()
+        {
+            return Ok(false);
+        }
+        match get_settlement_price(&e, &option) {
+            Ok(price) => Ok(is_in_the_money(&option, price)?),
+            Err(_) => Ok(false),
+        }
+    }
+
+    /// Black-Scholes premium for `quantity` units at the current oracle
+    /// price, in underlying token units. Volatility and risk-free rate are
+    /// annualized with 7 decimals, like strike prices.
+    pub fn quote_premium(
+        e: Env,
+        quantity: i128,
+        volatility: i128,
+        risk_free_rate: i128,
+    ) -> Result<i128, Error> {
+        let option = load_option(&e)?;
+        let quote = QuoteConfig {
+            volatility,
+            risk_free_rate,
+        };
+        pro_rata(&option, get_quoted_premium(&e, &option, &quote)?, quantity)
+    }
+
+    /// Black-Scholes delta, gamma, vega, theta and rho per escrow token at
+    /// the current oracle price, with 18 decimals. Inputs are annualized with
+    /// 7 decimals, as for `quote_premium`.
+    pub fn greeks(e: Env, volatility: i128, risk_free_rate: i128) -> Result<Greeks, Error> {
+        let option = load_option(&e)?;
+        let quote = QuoteConfig {
+            volatility,
+            risk_free_rate,
+        };
+        get_greeks(&e, &option, &quote)
+    }
+
+    /// Payoff of exercising at the current oracle price, in underlying token
+    /// units. Barrier options knocked out or not knocked in yet are worth 0.
+    pub fn intrinsic_value(e: Env) -> Result<i128, Error> {
+        let option = load_option(&e)?;
+        let price = get_spot_price(&e, &option)?;
+        if check_barrier(&e, &option).is_err() {
+            return Ok(0);
+        }
+        get_intrinsic_value(&option, price)
+    }
+}
+
+fn check_nonnegative_amount(amount: i128) -> Result<(), Error> {
+    if amount < 0 {
+        return Err(Error::InvalidAmount);
+    }
+    Ok(())
+}
+
+// Moves the option to its final state once every sold position is settled
+// and no units are left for sale.
+fn close_if_settled(e: &Env, option: &OptionInfo, to: OptionState) -> Result<(), Error> {
+    if read_outstanding(e) == 0 && read_unsold(e) == 0 {
+        transition(e, to)?;
+        return_bounty_pool(e, option);
+    }
+    Ok(())
+}
+
+// Bounties no keeper claimed go to the seller once the option is closed, as
+// nothing could pay them out anymore.
+fn return_bounty_pool(e: &Env, option: &OptionInfo) {
+    let pool = read_bounty_pool(e);
+    if pool > 0 {
+        write_bounty_pool(e, 0);
+        token::Client::new(e, &option.premium_token).transfer(
             &e.current_contract_address(),
             &option.seller,
-            &(option.escrow_amount as i128),
+            &pool,
         );
-        e.storage().instance().remove(&DataKey::OptionInfo);
     }
 }
 
+// Units held by the contract could never be exercised and their refund
+// would be paid back to the contract, so the contract may not hold any.
+fn check_recipient(e: &Env, to: &Address) -> Result<(), Error> {
+    if *to == e.current_contract_address() {
+        return Err(Error::InvalidRecipient);
+    }
+    Ok(())
+}
+
+// Page of holders settle can settle without them, with everything it reads
+// checked before any position is settled
+struct Settlement {
+    option: OptionInfo,
+    holders: Vec<Address>,
+    // Price in the money positions are exercised at during the exercise
+    // window, or None to expire every position once it has closed
+    exercise_price: Option<i128>,
+    keeper_bounty: i128,
+}
+
+// Finds the positions of up to `limit` holders from index `start` that can be
+// settled now. The settlement price may be fixed on the way, but nothing else
+// is written.
+fn check_settlement(e: &Env, start: u32, limit: u32) -> Result<Settlement, Error> {
+    let option = load_option(e)?;
+    check_transition(e, OptionState::Expired)?;
+    if !check_time_bound(e, &option)? {
+        return Err(Error::NotExpired);
+    }
+    let window_closed = is_exercise_window_closed(e, &option)?;
+    // Barrier options knocked out, or not knocked in by expiration, are
+    // worthless: their positions expire without waiting for the window
+    let worthless = check_barrier(e, &option).is_err();
+    // In the money positions are exercised for their holders, except when the
+    // holders still have to pay the strike: they must exercise themselves
+    let exercise_price = match option.settlement.strike_payment {
+        StrikePayment::AtPurchase if !window_closed && !worthless => {
+            match fix_settlement_price(e, &option) {
+                Ok(price) if is_in_the_money(&option, price)? => Some(price),
+                _ => None,
+            }
+        }
+        _ => None,
+    };
+    if !window_closed && !worthless && exercise_price.is_none() {
+        return Err(Error::NothingToSettle);
+    }
+
+    let mut holders = Vec::new(e);
+    let end = start.saturating_add(limit).min(read_holder_count(e));
+    for index in start..end {
+        if let Some(holder) = read_holder(e, index) {
+            holders.push_back(holder);
+        }
+    }
+    if holders.is_empty() {
+        return Err(Error::NothingToSettle);
+    }
+    Ok(Settlement {
+        option,
+        holders,
+        exercise_price,
+        keeper_bounty: read_fees(e)?.keeper_bounty,
+    })
+}
+
+// Settles a checked page of positions and pays the keeper's bounty out of the
+// pool.
+fn settle_positions(e: &Env, keeper: &Address, settlement: Settlement) -> Result<u32, Error> {
+    let option = &settlement.option;
+    // Paid first, as settling the last position returns the rest of the pool
+    let pool = read_bounty_pool(e);
+    let bounty = settlement.keeper_bounty.min(pool);
+    if bounty > 0 {
+        write_bounty_pool(e, pool - bounty);
+        token::Client::new(e, &option.premium_token).transfer(
+            &e.current_contract_address(),
+            keeper,
+            &bounty,
+        );
+    }
+
+    for holder in settlement.holders.iter() {
+        let units = read_balance(e, &holder);
+        match settlement.exercise_price {
+            Some(price) => exercise_position(e, option, &holder, units, price)?,
+            None => expire_position(e, option, &holder, units)?,
+        }
+    }
+    let settled = settlement.holders.len();
+    events::settled(e, keeper, settled, bounty);
+    Ok(settled)
+}
+
+// Burns exercised position units: the holder gets the escrow, or for a spread
+// their payoff, less the protocol fee, and the seller the holder's share of
+// the strike amount.
+fn exercise_position(
+    e: &Env,
+    option: &OptionInfo,
+    holder: &Address,
+    units: i128,
+    price: i128,
+) -> Result<(), Error> {
+    let (collateral_token, collateral) = take_collateral(e, option, units)?;
+    let deposit_share = close_position(e, holder, units)?;
+    let contract = e.current_contract_address();
+    let underlying_token_client = token::Client::new(e, &option.underlying_token);
+
+    // Send the holder's share of strikePrice * escrowAmount to seller,
+    // paid now by the holder when nothing was deposited at purchase
+    let strike_amount = match option.settlement.strike_payment {
+        StrikePayment::AtPurchase => {
+            if deposit_share > 0 {
+                underlying_token_client.transfer(&contract, &option.seller, &deposit_share);
+            }
+            deposit_share
+        }
+        StrikePayment::AtExercise => {
+            let strike_amount = pro_rata(option, get_deposit_amount(option)?, units)?;
+            if strike_amount > 0 {
+                underlying_token_client.transfer(holder, &option.seller, &strike_amount);
+            }
+            strike_amount
+        }
+    };
+
+    // Send the holder's units of escrowAmount to the holder, less the
+    // protocol fee. A spread pays out of the collateral only the capped
+    // payoff and returns the rest to the seller.
+    let payout = match option.kind {
+        OptionKind::Spread(_) => get_spread_payout(option, price, units)?.min(collateral),
+        _ => collateral,
+    };
+    let collateral_token_client = token::Client::new(e, &collateral_token);
+    let fees = read_fees(e)?;
+    let fee = fee_amount(e, payout, fees.exercise_bps)?;
+    if payout - fee > 0 {
+        collateral_token_client.transfer(&contract, holder, &(payout - fee));
+    }
+    if fee > 0 {
+        collateral_token_client.transfer(&contract, &fees.treasury, &fee);
+        events::fee(e, &fees.treasury, &collateral_token, fee);
+    }
+    if collateral > payout {
+        collateral_token_client.transfer(&contract, &option.seller, &(collateral - payout));
+    }
+
+    close_if_settled(e, option, OptionState::Exercised)?;
+
+    events::burn(e, holder, units);
+    events::exercised(
+        e,
+        holder,
+        price,
+        &collateral_token,
+        payout - fee,
+        strike_amount,
+    );
+    Ok(())
+}
+
+// Burns unexercised position units: the holder's share of the strike deposit
+// goes back to the holder and the matching escrow back to the seller.
+fn expire_position(
+    e: &Env,
+    option: &OptionInfo,
+    holder: &Address,
+    units: i128,
+) -> Result<(), Error> {
+    let (deposit_share, collateral_token, collateral) = release_position(e, option, holder, units)?;
+    events::expired(e, holder, deposit_share, &collateral_token, collateral);
+    Ok(())
+}
+
+// Burns position units without exercising them: the holder gets their strike
+// deposit back and the seller the collateral backing the units. Returns the
+// deposit share, the collateral token and the collateral returned.
+fn release_position(
+    e: &Env,
+    option: &OptionInfo,
+    holder: &Address,
+    units: i128,
+) -> Result<(i128, Address, i128), Error> {
+    let (collateral_token, collateral) = take_collateral(e, option, units)?;
+    let deposit_share = close_position(e, holder, units)?;
+    let contract = e.current_contract_address();
+
+    // Send the holder's share of strikePrice * escrowAmount back to the holder
+    if deposit_share > 0 {
+        token::Client::new(e, &option.underlying_token).transfer(&contract, holder, &deposit_share);
+    }
+
+    // Send the matching escrow, or underlying collateral, back to seller
+    if collateral > 0 {
+        token::Client::new(e, &collateral_token).transfer(&contract, &option.seller, &collateral);
+    }
+
+    close_if_settled(e, option, OptionState::Expired)?;
+
+    events::burn(e, holder, units);
+    Ok((deposit_share, collateral_token, collateral))
+}
+
 mod test;
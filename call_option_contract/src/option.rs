@@ -1,13 +1,208 @@
+use crate::asian::get_average_price;
+use crate::black_scholes::{call_greeks, call_price, Inputs};
+use crate::errors::Error;
+use crate::fixed_point::{pow10, rescale, wad_div, wad_mul, STRIKE_DECIMALS, WAD, WAD_DECIMALS};
+use crate::oracle;
+use crate::spread::{get_max_loss, take_collateral_share, write_collateral_held};
+use crate::storage_types::{
+    BinaryPayout, DataKey, Greeks, OptionInfo, OptionKind, OptionState, Premium, PriceSource,
+    QuoteConfig, INSTANCE_BUMP_AMOUNT, PERSISTENT_BUMP_AMOUNT,
+};
+use core::cmp::Ordering;
+use soroban_sdk::{token, Address, Env, Vec};
 
-use soroban_sdk::{ Env, Address, token};
-use crate::storage_types::{ DataKey, OptionInfo, INSTANCE_BUMP_AMOUNT};
+pub fn get_expiry(e: &Env, option: &OptionInfo) -> Result<u64, Error> {
+    option
+        .expiration_date
+        .checked_add(get_init_time(e)?)
+        .ok_or(Error::ArithmeticOverflow)
+}
+
+pub fn check_time_bound(e: &Env, option: &OptionInfo) -> Result<bool, Error> {
+    Ok(e.ledger().timestamp() >= get_expiry(e, option)?)
+}
+
+pub fn is_exercise_window_closed(e: &Env, option: &OptionInfo) -> Result<bool, Error> {
+    let window_end = get_expiry(e, option)?
+        .checked_add(option.settlement.exercise_window)
+        .ok_or(Error::ArithmeticOverflow)?;
+    Ok(e.ledger().timestamp() >= window_end)
+}
+
+// Settlement price fixed by an earlier exercise, settle or fix_price call
+pub fn read_settlement_price(e: &Env) -> Option<i128> {
+    e.storage().instance().bump(INSTANCE_BUMP_AMOUNT);
+    e.storage().instance().get(&DataKey::SettlementPrice)
+}
 
-pub fn check_time_bound(e: &Env) -> bool {
-    let option = load_option(e);
+// Reads the settlement price and keeps it for every later exercise and
+// settlement. The oracle only keeps its latest prices per asset, so whatever
+// the contract settles on is recorded while the oracle still has it: this
+// price, Asian fixings and barrier breaches.
+pub fn fix_settlement_price(e: &Env, option: &OptionInfo) -> Result<i128, Error> {
+    if let Some(price) = read_settlement_price(e) {
+        return Ok(price);
+    }
+    let price = get_settlement_price(e, option)?;
+    e.storage()
+        .instance()
+        .set(&DataKey::SettlementPrice, &price);
+    Ok(price)
+}
+
+// Settlement price fixed earlier, or else the one the oracle gives now
+pub fn get_settlement_price(e: &Env, option: &OptionInfo) -> Result<i128, Error> {
+    if let Some(price) = read_settlement_price(e) {
+        return Ok(price);
+    }
+    if let OptionKind::Asian(asian) = &option.kind {
+        return get_average_price(e, asian);
+    }
+    match &option.settlement.price_source {
+        PriceSource::Source(source) => get_source_price(e, option, *source),
+        PriceSource::Median(sources) => {
+            let mut prices = Vec::<i128>::new(e);
+            for source in sources.iter() {
+                prices.push_back(get_source_price(e, option, source)?);
+            }
+            median(&prices).ok_or(Error::NoPriceSources)
+        }
+    }
+}
+
+// Latest oracle price from the option's configured source(s), no older than
+// max_price_age but without the settlement check on timing. Used to value the
+// option before expiry.
+pub fn get_spot_price(e: &Env, option: &OptionInfo) -> Result<i128, Error> {
+    let oracle_client = oracle::Client::new(e, &option.oracle_contract_id);
+    let asset = oracle::Asset::Stellar(option.escrow_token.clone());
+    let mut prices = Vec::<i128>::new(e);
+    for source in get_sources(e, option).iter() {
+        let price_data = oracle_client
+            .lastprice_by_source(&source, &asset)
+            .ok_or(Error::PriceUnavailable)?;
+        let age = e.ledger().timestamp().saturating_sub(price_data.timestamp);
+        if age > option.settlement.max_price_age {
+            return Err(Error::StalePrice);
+        }
+        if price_data.price <= 0 {
+            return Err(Error::InvalidPrice);
+        }
+        prices.push_back(price_data.price);
+    }
+    median(&prices).ok_or(Error::NoPriceSources)
+}
+
+// Oracle sources the option reads its prices from
+pub fn get_sources(e: &Env, option: &OptionInfo) -> Vec<u32> {
+    match &option.settlement.price_source {
+        PriceSource::Source(source) => Vec::from_array(e, [*source]),
+        PriceSource::Median(sources) => sources.clone(),
+    }
+}
 
-    let current_timestamp = e.ledger().timestamp();
-    let init_timestamp = get_init_time(&e);
-    current_timestamp >= (option.expiration_date + init_timestamp)
+fn get_source_price(e: &Env, option: &OptionInfo, source: u32) -> Result<i128, Error> {
+    if option.settlement.twap_window > 0 {
+        get_twap_price(e, option, source)
+    } else {
+        get_expiry_price(e, option, source)
+    }
+}
+
+// First oracle price of the escrow token observed from expiration up to
+// max_price_age after it, rejected unless positive. Later prices are ignored,
+// so holders cannot wait for a better one during the exercise window.
+fn get_expiry_price(e: &Env, option: &OptionInfo, source: u32) -> Result<i128, Error> {
+    let oracle_client = oracle::Client::new(e, &option.oracle_contract_id);
+    let asset = oracle::Asset::Stellar(option.escrow_token.clone());
+    let expiry = get_expiry(e, option)?;
+    let end = expiry
+        .checked_add(option.settlement.max_price_age)
+        .ok_or(Error::ArithmeticOverflow)?;
+    let price_data = match oracle_client
+        .prices_by_source(&source, &asset, &expiry, &end)
+        .first()
+    {
+        Some(price_data) => price_data,
+        None => {
+            return Err(match oracle_client.lastprice_by_source(&source, &asset) {
+                None => Error::PriceUnavailable,
+                Some(last) if last.timestamp < expiry => Error::PriceBeforeExpiry,
+                // Only published too long after expiration
+                Some(_) => Error::StalePrice,
+            });
+        }
+    };
+    if price_data.price <= 0 {
+        return Err(Error::InvalidPrice);
+    }
+    Ok(price_data.price)
+}
+
+// Time-weighted average of the oracle prices in the twap_window ending at
+// expiration. Each price is weighted by the time until the next one, the last
+// by the time until expiration; if they all share one timestamp the plain
+// average is used.
+fn get_twap_price(e: &Env, option: &OptionInfo, source: u32) -> Result<i128, Error> {
+    let oracle_client = oracle::Client::new(e, &option.oracle_contract_id);
+    let asset = oracle::Asset::Stellar(option.escrow_token.clone());
+    let end = get_expiry(e, option)?;
+    let start = end.saturating_sub(option.settlement.twap_window);
+    let prices = oracle_client.prices_by_source(&source, &asset, &start, &end);
+    if prices.is_empty() || prices.len() < option.settlement.min_samples {
+        return Err(Error::InsufficientSamples);
+    }
+
+    for price_data in prices.iter() {
+        if price_data.price <= 0 {
+            return Err(Error::InvalidPrice);
+        }
+    }
+    time_weighted_average(&prices, end).ok_or(Error::ArithmeticOverflow)
+}
+
+fn time_weighted_average(prices: &Vec<oracle::PriceData>, end: u64) -> Option<i128> {
+    let mut weighted_sum: i128 = 0;
+    let mut total_weight: i128 = 0;
+    let mut sum: i128 = 0;
+    for i in 0..prices.len() {
+        let price_data = prices.get(i)?;
+        let next_timestamp = match prices.get(i + 1) {
+            Some(next) => next.timestamp,
+            None => end,
+        };
+        let weight = next_timestamp.saturating_sub(price_data.timestamp) as i128;
+        weighted_sum = weighted_sum.checked_add(price_data.price.checked_mul(weight)?)?;
+        total_weight = total_weight.checked_add(weight)?;
+        sum = sum.checked_add(price_data.price)?;
+    }
+    if total_weight == 0 {
+        sum.checked_div(prices.len() as i128)
+    } else {
+        weighted_sum.checked_div(total_weight)
+    }
+}
+
+// Median of the given values, averaging the two middle ones for an even count
+pub fn median(values: &Vec<i128>) -> Option<i128> {
+    let mut sorted = Vec::<i128>::new(values.env());
+    for value in values.iter() {
+        let mut index = 0;
+        while index < sorted.len() && sorted.get(index)? < value {
+            index += 1;
+        }
+        sorted.insert(index, value);
+    }
+    let middle = sorted.len() / 2;
+    if sorted.is_empty() {
+        None
+    } else if sorted.len() % 2 == 1 {
+        sorted.get(middle)
+    } else {
+        let low = sorted.get(middle - 1)?;
+        let high = sorted.get(middle)?;
+        Some(low + (high - low) / 2)
+    }
 }
 
 pub fn save_option(e: &Env, option: &OptionInfo) {
@@ -15,19 +210,71 @@ pub fn save_option(e: &Env, option: &OptionInfo) {
     e.storage().instance().set(&DataKey::OptionInfo, option);
 }
 
-pub fn load_option(e: &Env) -> OptionInfo {
+pub fn load_option(e: &Env) -> Result<OptionInfo, Error> {
     e.storage().instance().bump(INSTANCE_BUMP_AMOUNT);
-    e.storage().instance().get(&DataKey::OptionInfo).unwrap()
+    e.storage()
+        .instance()
+        .get(&DataKey::OptionInfo)
+        .ok_or(Error::NotInitialized)
 }
 
-pub fn set_buyer(e: &Env, buyer: &Address) {
+pub fn get_state(e: &Env) -> Result<OptionState, Error> {
     e.storage().instance().bump(INSTANCE_BUMP_AMOUNT);
-    e.storage().instance().set(&DataKey::Buyer, buyer);
+    e.storage()
+        .instance()
+        .get(&DataKey::State)
+        .ok_or(Error::NotInitialized)
+}
+
+// Whether the option may move to the given state. Every lifecycle change is
+// checked here; the allowed transitions are
+//   (new) -> Open -> Purchased -> Exercised | Expired
+//            Open -> Cancelled
+// The first sale moves the series to Purchased and later sales keep it there.
+// Positions settle one holder at a time, so settlement checks it can reach
+// Exercised or Expired before the last holder actually moves it there.
+pub fn check_transition(e: &Env, to: OptionState) -> Result<(), Error> {
+    let from: Option<OptionState> = e.storage().instance().get(&DataKey::State);
+    match (from, to) {
+        (None, OptionState::Open)
+        | (Some(OptionState::Open), OptionState::Purchased)
+        | (Some(OptionState::Open), OptionState::Cancelled)
+        | (Some(OptionState::Purchased), OptionState::Exercised)
+        | (Some(OptionState::Purchased), OptionState::Expired) => Ok(()),
+        (None, _) => Err(Error::NotInitialized),
+        (Some(_), OptionState::Open) => Err(Error::AlreadyInitialized),
+        (Some(OptionState::Open), _) => Err(Error::NotPurchased),
+        (Some(OptionState::Purchased), _) => Err(Error::AlreadyPurchased),
+        _ => Err(Error::OptionClosed),
+    }
 }
 
-pub fn get_buyer(e: &Env) -> Address {
+pub fn transition(e: &Env, to: OptionState) -> Result<(), Error> {
+    check_transition(e, to)?;
     e.storage().instance().bump(INSTANCE_BUMP_AMOUNT);
-    e.storage().instance().get(&DataKey::Buyer).unwrap()
+    e.storage().instance().set(&DataKey::State, &to);
+    Ok(())
+}
+
+// Adds to the units a buyer bought over all their purchases
+pub fn add_bought(e: &Env, buyer: &Address, units: i128) -> Result<(), Error> {
+    let bought = get_bought(e, buyer)
+        .checked_add(units)
+        .ok_or(Error::ArithmeticOverflow)?;
+    let key = DataKey::Bought(buyer.clone());
+    e.storage().persistent().set(&key, &bought);
+    e.storage().persistent().bump(&key, PERSISTENT_BUMP_AMOUNT);
+    Ok(())
+}
+
+pub fn get_bought(e: &Env, buyer: &Address) -> i128 {
+    let key = DataKey::Bought(buyer.clone());
+    if let Some(bought) = e.storage().persistent().get::<DataKey, i128>(&key) {
+        e.storage().persistent().bump(&key, PERSISTENT_BUMP_AMOUNT);
+        bought
+    } else {
+        0
+    }
 }
 
 pub fn set_init_time(e: &Env, time: &u64) {
@@ -35,25 +282,207 @@ pub fn set_init_time(e: &Env, time: &u64) {
     e.storage().instance().set(&DataKey::InitTime, time);
 }
 
-pub fn get_init_time(e: &Env) -> u64 {
+pub fn get_init_time(e: &Env) -> Result<u64, Error> {
     e.storage().instance().bump(INSTANCE_BUMP_AMOUNT);
-    e.storage().instance().get(&DataKey::InitTime).unwrap()
+    e.storage()
+        .instance()
+        .get(&DataKey::InitTime)
+        .ok_or(Error::NotInitialized)
 }
 
-
-pub fn deposite_escrow(e: &Env) {
-    let option = load_option(e);
-    let seller = option.seller;
+pub fn deposite_escrow(e: &Env, option: &OptionInfo) -> Result<(), Error> {
+    let seller = &option.seller;
     let contract = e.current_contract_address();
-    let escrow_token_client = token::Client::new(e, &option.escrow_token);
     seller.require_auth();
-    escrow_token_client.transfer(&seller, &contract, &(option.escrow_amount as i128));
+    if let Some(collateral) = get_cash_collateral(option)? {
+        token::Client::new(e, &option.underlying_token).transfer(seller, &contract, &collateral);
+        write_collateral_held(e, collateral);
+        return Ok(());
+    }
+    let escrow_token_client = token::Client::new(e, &option.escrow_token);
+    escrow_token_client.transfer(seller, &contract, &option.escrow_amount);
+    Ok(())
+}
+
+// Collateral a seller deposits in the underlying token instead of the
+// escrow: a spread's max loss or a binary option's fixed payout.
+fn get_cash_collateral(option: &OptionInfo) -> Result<Option<i128>, Error> {
+    match option.kind {
+        OptionKind::Spread(_) => get_max_loss(option).map(Some),
+        OptionKind::Binary(_) => get_strike_value(option).map(Some),
+        _ => Ok(None),
+    }
+}
+
+// Seller collateral backing `units` of the escrow and the token it is held
+// in: the escrow units themselves, or their share of the underlying token
+// collateral. Must be taken before the units are settled.
+pub fn take_collateral(
+    e: &Env,
+    option: &OptionInfo,
+    units: i128,
+) -> Result<(Address, i128), Error> {
+    if let OptionKind::Spread(_) | OptionKind::Binary(_) = option.kind {
+        let share = take_collateral_share(e, units)?;
+        return Ok((option.underlying_token.clone(), share));
+    }
+    Ok((option.escrow_token.clone(), units))
+}
+
+// Value of the whole escrow at the strike, escrow_amount * strike_price,
+// converted from escrow token units to underlying token units.
+pub fn get_strike_value(option: &OptionInfo) -> Result<i128, Error> {
+    to_underlying_amount(option, option.strike_price, STRIKE_DECIMALS)
+}
+
+// Underlying token amount deposited for the whole series, the strike value
+pub fn get_deposit_amount(option: &OptionInfo) -> Result<i128, Error> {
+    // Binary and spread buyers only pay the premium, their payoff comes out
+    // of the seller's collateral
+    if let OptionKind::Binary(_) | OptionKind::Spread(_) = option.kind {
+        return Ok(0);
+    }
+    get_strike_value(option)
+}
+
+// Share of a series-wide amount for `units` of the escrow, rounded up so the
+// seller is never paid less than the exact pro-rata amount.
+pub fn pro_rata(option: &OptionInfo, amount: i128, units: i128) -> Result<i128, Error> {
+    let share = || {
+        amount
+            .checked_mul(units)?
+            .checked_add(option.escrow_amount - 1)?
+            .checked_div(option.escrow_amount)
+    };
+    share().ok_or(Error::ArithmeticOverflow)
+}
+
+// Premium for the whole escrow: the fixed amount, or the Black-Scholes quote
+// at the current oracle price.
+pub fn get_premium(e: &Env, option: &OptionInfo) -> Result<i128, Error> {
+    match &option.premium {
+        Premium::Fixed(premium) => Ok(*premium),
+        Premium::Quoted(quote) => get_quoted_premium(e, option, quote),
+    }
+}
+
+// Black-Scholes value of the whole escrow at the current oracle price, in
+// underlying token units.
+pub fn get_quoted_premium(
+    e: &Env,
+    option: &OptionInfo,
+    quote: &QuoteConfig,
+) -> Result<i128, Error> {
+    let inputs = get_pricing_inputs(e, option, quote)?;
+    let price = call_price(&inputs)
+        .and_then(|price| wad_mul(price, option.strike_price))
+        .ok_or(Error::ArithmeticOverflow)?;
+    to_underlying_amount(option, price, STRIKE_DECIMALS)
+}
+
+// Black-Scholes Greeks per escrow token at the current oracle price.
+pub fn get_greeks(e: &Env, option: &OptionInfo, quote: &QuoteConfig) -> Result<Greeks, Error> {
+    let inputs = get_pricing_inputs(e, option, quote)?;
+    let greeks = || {
+        let strike = rescale(option.strike_price, STRIKE_DECIMALS, WAD_DECIMALS)?;
+        let greeks = call_greeks(&inputs)?;
+        Some(Greeks {
+            delta: greeks.delta,
+            gamma: wad_div(greeks.gamma, strike)?,
+            vega: wad_mul(greeks.vega, strike)?,
+            theta: wad_mul(greeks.theta, strike)?,
+            rho: wad_mul(greeks.rho, strike)?,
+        })
+    };
+    greeks().ok_or(Error::ArithmeticOverflow)
+}
+
+const SECONDS_PER_YEAR: i128 = 365 * 24 * 60 * 60;
+
+// Model inputs from the option terms, the oracle spot price and the time
+// left to expiry.
+pub fn get_pricing_inputs(
+    e: &Env,
+    option: &OptionInfo,
+    quote: &QuoteConfig,
+) -> Result<Inputs, Error> {
+    if quote.volatility < 0 {
+        return Err(Error::InvalidAmount);
+    }
+    if option.kind != OptionKind::Vanilla {
+        return Err(Error::QuoteUnsupported);
+    }
+    let spot = get_spot_price(e, option)?;
+    let seconds_left = get_expiry(e, option)?.saturating_sub(e.ledger().timestamp());
+    let inputs = || {
+        Some(Inputs {
+            moneyness: rescale(spot, option.oracle_decimals, WAD_DECIMALS + STRIKE_DECIMALS)?
+                .checked_div(option.strike_price)?,
+            time: i128::from(seconds_left).checked_mul(WAD)? / SECONDS_PER_YEAR,
+            rate: rescale(quote.risk_free_rate, STRIKE_DECIMALS, WAD_DECIMALS)?,
+            volatility: rescale(quote.volatility, STRIKE_DECIMALS, WAD_DECIMALS)?,
+        })
+    };
+    inputs().ok_or(Error::ArithmeticOverflow)
+}
+
+// Value of the whole escrow at a per-token price with the given decimals, in
+// underlying token units.
+pub fn to_underlying_amount(
+    option: &OptionInfo,
+    price: i128,
+    decimals: u32,
+) -> Result<i128, Error> {
+    let amount = || {
+        option
+            .escrow_amount
+            .checked_mul(price)?
+            .checked_mul(pow10(option.underlying_decimals)?)?
+            .checked_div(pow10(decimals.checked_add(option.escrow_decimals)?)?)
+    };
+    amount().ok_or(Error::ArithmeticOverflow)
+}
+
+// Payoff of exercising at the given oracle price, in underlying token units:
+// escrow_amount * (price - strike_price) with the price capped at the upper
+// strike of a spread, the fixed payout of a binary option, or 0 when out of
+// the money. The price is truncated to STRIKE_DECIMALS first,
+// as a finer oracle scale would overflow on large escrows.
+pub fn get_intrinsic_value(option: &OptionInfo, price: i128) -> Result<i128, Error> {
+    if !is_in_the_money(option, price)? {
+        return Ok(0);
+    }
+    let price =
+        rescale(price, option.oracle_decimals, STRIKE_DECIMALS).ok_or(Error::ArithmeticOverflow)?;
+    let value = match &option.kind {
+        OptionKind::Binary(_) => option.strike_price,
+        OptionKind::Spread(spread) => price.min(spread.upper_strike) - option.strike_price,
+        _ => price - option.strike_price,
+    };
+    to_underlying_amount(option, value.max(0), STRIKE_DECIMALS)
+}
+
+// Whether exercising at an oracle price pays off
+pub fn is_in_the_money(option: &OptionInfo, price: i128) -> Result<bool, Error> {
+    match option.kind {
+        OptionKind::Binary(BinaryPayout::Below) => is_below_strike(option, price),
+        _ => Ok(!is_below_strike(option, price)?),
+    }
 }
 
-pub fn is_initialized(e: &Env) -> bool {
-    e.storage().instance().has(&DataKey::OptionInfo)
+// Whether an oracle price is below the strike
+pub fn is_below_strike(option: &OptionInfo, price: i128) -> Result<bool, Error> {
+    Ok(compare_to_price(option, price, option.strike_price)? == Ordering::Less)
 }
 
-pub fn is_buyer_entered(e: &Env) -> bool {
-    e.storage().instance().has(&DataKey::Buyer)
+// Compares an oracle price to a price with STRIKE_DECIMALS, at the finer of
+// the two decimal scales so neither side loses precision.
+pub fn compare_to_price(option: &OptionInfo, price: i128, other: i128) -> Result<Ordering, Error> {
+    let decimals = option.oracle_decimals.max(STRIKE_DECIMALS);
+    let price = rescale(price, option.oracle_decimals, decimals);
+    let other = rescale(other, STRIKE_DECIMALS, decimals);
+    match (price, other) {
+        (Some(price), Some(other)) => Ok(price.cmp(&other)),
+        _ => Err(Error::ArithmeticOverflow),
+    }
 }
@@ -0,0 +1,235 @@
+use crate::errors::Error;
+use crate::storage_types::{
+    AllowanceDataKey, AllowanceValue, DataKey, INSTANCE_BUMP_AMOUNT, PERSISTENT_BUMP_AMOUNT,
+};
+use soroban_sdk::{Address, Env};
+
+pub fn read_balance(e: &Env, id: &Address) -> i128 {
+    let key = DataKey::Balance(id.clone());
+    if let Some(balance) = e.storage().persistent().get::<DataKey, i128>(&key) {
+        e.storage().persistent().bump(&key, PERSISTENT_BUMP_AMOUNT);
+        balance
+    } else {
+        0
+    }
+}
+
+fn write_balance(e: &Env, id: &Address, amount: i128) {
+    let key = DataKey::Balance(id.clone());
+    e.storage().persistent().set(&key, &amount);
+    e.storage().persistent().bump(&key, PERSISTENT_BUMP_AMOUNT);
+}
+
+pub fn receive_balance(e: &Env, id: &Address, amount: i128) -> Result<(), Error> {
+    let balance = read_balance(e, id);
+    if balance == 0 && amount > 0 {
+        add_holder(e, id);
+    }
+    let balance = balance
+        .checked_add(amount)
+        .ok_or(Error::ArithmeticOverflow)?;
+    write_balance(e, id, balance);
+    Ok(())
+}
+
+// Number of addresses holding position units
+pub fn read_holder_count(e: &Env) -> u32 {
+    e.storage().instance().bump(INSTANCE_BUMP_AMOUNT);
+    e.storage()
+        .instance()
+        .get(&DataKey::HolderCount)
+        .unwrap_or(0)
+}
+
+fn write_holder_count(e: &Env, count: u32) {
+    e.storage().instance().bump(INSTANCE_BUMP_AMOUNT);
+    e.storage().instance().set(&DataKey::HolderCount, &count);
+}
+
+// Holder at `index`, below read_holder_count
+pub fn read_holder(e: &Env, index: u32) -> Option<Address> {
+    let key = DataKey::Holder(index);
+    let holder = e.storage().persistent().get::<DataKey, Address>(&key)?;
+    e.storage().persistent().bump(&key, PERSISTENT_BUMP_AMOUNT);
+    Some(holder)
+}
+
+fn write_holder(e: &Env, index: u32, id: &Address) {
+    let key = DataKey::Holder(index);
+    e.storage().persistent().set(&key, id);
+    e.storage().persistent().bump(&key, PERSISTENT_BUMP_AMOUNT);
+    let key = DataKey::HolderIndex(id.clone());
+    e.storage().persistent().set(&key, &index);
+    e.storage().persistent().bump(&key, PERSISTENT_BUMP_AMOUNT);
+}
+
+fn add_holder(e: &Env, id: &Address) {
+    let count = read_holder_count(e);
+    write_holder(e, count, id);
+    write_holder_count(e, count + 1);
+}
+
+// Moves the last holder into the slot of `id`, so the indexes stay dense
+fn remove_holder(e: &Env, id: &Address) {
+    let index_key = DataKey::HolderIndex(id.clone());
+    let index = match e.storage().persistent().get::<DataKey, u32>(&index_key) {
+        Some(index) => index,
+        None => return,
+    };
+    let last = read_holder_count(e) - 1;
+    if index != last {
+        if let Some(moved) = read_holder(e, last) {
+            write_holder(e, index, &moved);
+        }
+    }
+    e.storage().persistent().remove(&DataKey::Holder(last));
+    e.storage().persistent().remove(&index_key);
+    write_holder_count(e, last);
+}
+
+pub fn spend_balance(e: &Env, id: &Address, amount: i128) -> Result<(), Error> {
+    let balance = read_balance(e, id);
+    if balance < amount {
+        return Err(Error::InsufficientBalance);
+    }
+    write_balance(e, id, balance - amount);
+    if balance > 0 && balance == amount {
+        remove_holder(e, id);
+    }
+    Ok(())
+}
+
+pub fn read_allowance(e: &Env, from: &Address, spender: &Address) -> AllowanceValue {
+    let key = DataKey::Allowance(AllowanceDataKey {
+        from: from.clone(),
+        spender: spender.clone(),
+    });
+    match e.storage().temporary().get::<DataKey, AllowanceValue>(&key) {
+        Some(allowance) if allowance.expiration_ledger >= e.ledger().sequence() => allowance,
+        Some(allowance) => AllowanceValue {
+            amount: 0,
+            expiration_ledger: allowance.expiration_ledger,
+        },
+        None => AllowanceValue {
+            amount: 0,
+            expiration_ledger: 0,
+        },
+    }
+}
+
+pub fn write_allowance(
+    e: &Env,
+    from: &Address,
+    spender: &Address,
+    amount: i128,
+    expiration_ledger: u32,
+) -> Result<(), Error> {
+    if amount > 0 && expiration_ledger < e.ledger().sequence() {
+        return Err(Error::InvalidExpirationLedger);
+    }
+    let key = DataKey::Allowance(AllowanceDataKey {
+        from: from.clone(),
+        spender: spender.clone(),
+    });
+    let allowance = AllowanceValue {
+        amount,
+        expiration_ledger,
+    };
+    e.storage().temporary().set(&key, &allowance);
+    if amount > 0 {
+        let live_for = expiration_ledger - e.ledger().sequence();
+        e.storage().temporary().bump(&key, live_for);
+    }
+    Ok(())
+}
+
+pub fn spend_allowance(
+    e: &Env,
+    from: &Address,
+    spender: &Address,
+    amount: i128,
+) -> Result<(), Error> {
+    let allowance = read_allowance(e, from, spender);
+    if allowance.amount < amount {
+        return Err(Error::InsufficientAllowance);
+    }
+    write_allowance(
+        e,
+        from,
+        spender,
+        allowance.amount - amount,
+        allowance.expiration_ledger,
+    )
+}
+
+pub fn read_outstanding(e: &Env) -> i128 {
+    e.storage().instance().bump(INSTANCE_BUMP_AMOUNT);
+    e.storage()
+        .instance()
+        .get(&DataKey::Outstanding)
+        .unwrap_or(0)
+}
+
+pub fn read_unsold(e: &Env) -> i128 {
+    e.storage().instance().bump(INSTANCE_BUMP_AMOUNT);
+    e.storage().instance().get(&DataKey::Unsold).unwrap_or(0)
+}
+
+pub fn write_unsold(e: &Env, units: i128) {
+    e.storage().instance().bump(INSTANCE_BUMP_AMOUNT);
+    e.storage().instance().set(&DataKey::Unsold, &units);
+}
+
+fn read_deposit_held(e: &Env) -> i128 {
+    e.storage()
+        .instance()
+        .get(&DataKey::DepositHeld)
+        .unwrap_or(0)
+}
+
+// Mints position units to a buyer, backed by their strike deposit
+pub fn open_position(
+    e: &Env,
+    buyer: &Address,
+    units: i128,
+    deposit_amount: i128,
+) -> Result<(), Error> {
+    receive_balance(e, buyer, units)?;
+    let outstanding = read_outstanding(e)
+        .checked_add(units)
+        .ok_or(Error::ArithmeticOverflow)?;
+    let deposit_held = read_deposit_held(e)
+        .checked_add(deposit_amount)
+        .ok_or(Error::ArithmeticOverflow)?;
+    e.storage()
+        .instance()
+        .set(&DataKey::Outstanding, &outstanding);
+    e.storage()
+        .instance()
+        .set(&DataKey::DepositHeld, &deposit_held);
+    Ok(())
+}
+
+// Burns `units` of the holder's position and returns the matching share of
+// the strike deposits held. The last units settled take whatever deposit is
+// left, so rounding never strands funds in the contract.
+pub fn close_position(e: &Env, holder: &Address, units: i128) -> Result<i128, Error> {
+    spend_balance(e, holder, units)?;
+    let outstanding = read_outstanding(e);
+    let deposit_held = read_deposit_held(e);
+    let deposit_share = if units == outstanding {
+        deposit_held
+    } else {
+        deposit_held
+            .checked_mul(units)
+            .ok_or(Error::ArithmeticOverflow)?
+            / outstanding
+    };
+    e.storage()
+        .instance()
+        .set(&DataKey::Outstanding, &(outstanding - units));
+    e.storage()
+        .instance()
+        .set(&DataKey::DepositHeld, &(deposit_held - deposit_share));
+    Ok(deposit_share)
+}
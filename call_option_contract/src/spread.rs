@@ -0,0 +1,73 @@
+use crate::errors::Error;
+use crate::fixed_point::STRIKE_DECIMALS;
+use crate::option::{get_intrinsic_value, to_underlying_amount};
+use crate::position::{read_outstanding, read_unsold};
+use crate::storage_types::{DataKey, OptionInfo, OptionKind, INSTANCE_BUMP_AMOUNT};
+use soroban_sdk::Env;
+
+fn read_collateral_held(e: &Env) -> i128 {
+    e.storage().instance().bump(INSTANCE_BUMP_AMOUNT);
+    e.storage()
+        .instance()
+        .get(&DataKey::CollateralHeld)
+        .unwrap_or(0)
+}
+
+pub fn write_collateral_held(e: &Env, amount: i128) {
+    e.storage().instance().bump(INSTANCE_BUMP_AMOUNT);
+    e.storage()
+        .instance()
+        .set(&DataKey::CollateralHeld, &amount);
+}
+
+// Most the seller can lose on the whole escrow, in underlying token units:
+// escrow_amount * (upper_strike - strike_price).
+pub fn get_max_loss(option: &OptionInfo) -> Result<i128, Error> {
+    match &option.kind {
+        OptionKind::Spread(spread) => {
+            let width = spread
+                .upper_strike
+                .checked_sub(option.strike_price)
+                .ok_or(Error::ArithmeticOverflow)?;
+            to_underlying_amount(option, width, STRIKE_DECIMALS)
+        }
+        _ => Err(Error::InvalidSpread),
+    }
+}
+
+// The upper strike must be above the strike by enough to collateralize
+pub fn check_spread_terms(option: &OptionInfo) -> Result<(), Error> {
+    let spread = match &option.kind {
+        OptionKind::Spread(spread) => spread,
+        _ => return Ok(()),
+    };
+    if spread.upper_strike <= option.strike_price || get_max_loss(option)? <= 0 {
+        return Err(Error::InvalidSpread);
+    }
+    Ok(())
+}
+
+// Share of the seller's collateral backing `units`, removed from what is
+// held. Must be taken before the units are settled; the last units take
+// whatever is left, like close_position does with the strike deposits.
+pub fn take_collateral_share(e: &Env, units: i128) -> Result<i128, Error> {
+    let held = read_collateral_held(e);
+    let remaining = read_outstanding(e) + read_unsold(e);
+    let share = if units == remaining {
+        held
+    } else {
+        held.checked_mul(units).ok_or(Error::ArithmeticOverflow)? / remaining
+    };
+    write_collateral_held(e, held - share);
+    Ok(share)
+}
+
+// Payoff owed to the holder of `units` at an oracle price, rounded down:
+// their share of escrow_amount * (min(price, upper_strike) - strike_price).
+pub fn get_spread_payout(option: &OptionInfo, price: i128, units: i128) -> Result<i128, Error> {
+    let payoff = get_intrinsic_value(option, price)?;
+    payoff
+        .checked_mul(units)
+        .ok_or(Error::ArithmeticOverflow)
+        .map(|amount| amount / option.escrow_amount)
+}
@@ -1,10 +1,153 @@
-use soroban_sdk::{ contracttype, Address, String, Env};
+use soroban_sdk::{contracttype, Address, Env, String, Vec};
 
 pub(crate) const TEMPORARY_BUMP_AMOUNT: u32 = 17280; // 1 day
 pub(crate) const INSTANCE_BUMP_AMOUNT: u32 = 34560; // 2 days
 pub(crate) const PERSISTENT_BUMP_AMOUNT: u32 = 518400; // 30 days
 
-#[derive(Clone)]
+// Layout of the stored data written by this code. Bump it along with a step in
+// `upgrade::migrate_storage` whenever a stored type such as `OptionInfo` changes.
+pub(crate) const STORAGE_VERSION: u32 = 2;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub enum PriceSource {
+    // Settle on a single oracle source
+    Source(u32),
+    // Settle on the median of the prices of several oracle sources
+    Median(Vec<u32>),
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[contracttype]
+pub enum StrikePayment {
+    // Buyers deposit the strike amount when they buy
+    AtPurchase = 0,
+    // Buyers pay the strike amount only when they exercise
+    AtExercise = 1,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct SettlementConfig {
+    // Seconds after expiration during which the buyer can exercise
+    pub exercise_window: u64,
+    // Maximum age in seconds of the oracle prices read; the settlement price
+    // is the first one published within this long after expiration
+    pub max_price_age: u64,
+    // Length in seconds of the window ending at expiration whose oracle prices
+    // are time-weighted into the settlement price; 0 settles on the last price
+    pub twap_window: u64,
+    // Minimum number of oracle prices required in the TWAP window, 0 without one
+    pub min_samples: u32,
+    // Oracle source(s) the settlement price is read from
+    pub price_source: PriceSource,
+    // When buyers pay escrow_amount * strike_price
+    pub strike_payment: StrikePayment,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[contracttype]
+pub enum BarrierType {
+    // Worthless once the price rises to the level
+    UpAndOut = 0,
+    // Worthless unless the price rises to the level
+    UpAndIn = 1,
+    // Worthless once the price falls to the level
+    DownAndOut = 2,
+    // Worthless unless the price falls to the level
+    DownAndIn = 3,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct BarrierConfig {
+    pub barrier_type: BarrierType,
+    // Barrier price in underlying tokens per escrow token, with
+    // STRIKE_DECIMALS like the strike price
+    pub level: i128,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct AsianConfig {
+    // Fixing times in seconds after creation, increasing and no later than
+    // the expiration date
+    pub fixings: Vec<u64>,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[contracttype]
+pub enum BinaryPayout {
+    // Pays when the settlement price is at or above the strike
+    Above = 0,
+    // Pays when the settlement price is below the strike
+    Below = 1,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct SpreadConfig {
+    // Strike of the short call, above the option strike, with STRIKE_DECIMALS
+    pub upper_strike: i128,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub enum OptionKind {
+    // Plain call paying off on the settlement price
+    Vanilla,
+    // Call knocked in or out by the oracle price between creation and
+    // expiration
+    Barrier(BarrierConfig),
+    // Call paying off on the average of the oracle prices at scheduled
+    // fixings instead of the settlement price
+    Asian(AsianConfig),
+    // Cash-or-nothing option: the seller posts the strike value of the
+    // escrow, escrow_amount * strike_price in the underlying token, as the
+    // fixed payout delivered when the price ends on the paying side
+    Binary(BinaryPayout),
+    // Bull call spread, long the strike and short the upper strike: settled
+    // in the underlying token out of the seller's capped max loss
+    Spread(SpreadConfig),
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct QuoteConfig {
+    // Annualized volatility, with 7 decimals like strike prices
+    pub volatility: i128,
+    // Annualized continuously compounded risk-free rate, with 7 decimals
+    pub risk_free_rate: i128,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub enum Premium {
+    // Premium for the whole escrow, in premium token units
+    Fixed(i128),
+    // Black-Scholes premium quoted from the oracle price at purchase, paid in
+    // the underlying token
+    Quoted(QuoteConfig),
+}
+
+// Sensitivities of the option value per escrow token, with 18 decimals. Price
+// values are in underlying tokens per escrow token.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct Greeks {
+    // Change in value per unit change of the spot price
+    pub delta: i128,
+    // Change in delta per unit change of the spot price
+    pub gamma: i128,
+    // Change in value per unit (100%) change of volatility
+    pub vega: i128,
+    // Change in value per year passing
+    pub theta: i128,
+    // Change in value per unit (100%) change of the risk-free rate
+    pub rho: i128,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
 #[contracttype]
 pub struct OptionInfo {
     // Owner of this option
@@ -14,22 +157,102 @@ pub struct OptionInfo {
     // Underlying token in escrow
     pub underlying_token: Address,
     // Stoking amount in escrow
-    pub escrow_amount: u32,
-    // Strike price in this option
-    pub strike_price: u32,
+    pub escrow_amount: i128,
+    // Strike price in underlying tokens per escrow token, with STRIKE_DECIMALS
+    pub strike_price: i128,
     // Timestamp of expiration Date
     pub expiration_date: u64,
+    // How and when the option settles against the oracle
+    pub settlement: SettlementConfig,
+    // Payoff of the option
+    pub kind: OptionKind,
     // Option Fee
-    pub premium: u32,
+    pub premium: Premium,
+    // Token the premium is paid in
+    pub premium_token: Address,
     // Oracle contract id
-    pub oracle_contract_id: Address
+    pub oracle_contract_id: Address,
+    // Decimals of the oracle prices
+    pub oracle_decimals: u32,
+    // Decimals of the escrow token
+    pub escrow_decimals: u32,
+    // Decimals of the underlying token
+    pub underlying_decimals: u32,
 }
 
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[contracttype]
+pub enum OptionState {
+    // Escrow deposited, waiting for a buyer
+    Open = 0,
+    // Some units sold: buyers paid premium and strike deposit for them
+    Purchased = 1,
+    // Last position exercised: escrow went to the holder, strike deposit to
+    // the seller
+    Exercised = 2,
+    // Last position or unsold remainder returned: collateral back to both
+    // sides
+    Expired = 3,
+    // Seller withdrew the escrow before anyone bought
+    Cancelled = 4,
+}
+
+#[derive(Clone)]
+#[contracttype]
+pub struct AllowanceDataKey {
+    pub from: Address,
+    pub spender: Address,
+}
+
+#[derive(Clone)]
+#[contracttype]
+pub struct AllowanceValue {
+    pub amount: i128,
+    pub expiration_ledger: u32,
+}
+
+// Escrow and underlying token of an option
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct TokenPair {
+    pub escrow_token: Address,
+    pub underlying_token: Address,
+}
 
 #[derive(Clone)]
 #[contracttype]
 pub enum DataKey {
     OptionInfo,
-    Buyer,
+    // Units bought by each buyer
+    Bought(Address),
     InitTime,
-}
\ No newline at end of file
+    State,
+    // Position units held by an address, one per escrow token unit
+    Balance(Address),
+    Allowance(AllowanceDataKey),
+    // Position units not settled yet
+    Outstanding,
+    // Part of the buyers' strike deposits not settled yet
+    DepositHeld,
+    // Escrow units still for sale
+    Unsold,
+    // Address holding position units at each index below HolderCount, for
+    // keepers to settle
+    Holder(u32),
+    // Number of addresses holding position units
+    HolderCount,
+    // Index of a holder's Holder key
+    HolderIndex(Address),
+    // Premium tokens set aside for keeper bounties
+    BountyPool,
+    // STORAGE_VERSION of the stored data
+    Version,
+    // Whether the barrier was reached, once a trigger_barrier call proved it
+    BarrierHit,
+    // Oracle price recorded at each fixing of an Asian option
+    Fixing(u32),
+    // Part of a spread or binary seller's underlying collateral not settled yet
+    CollateralHeld,
+    // Settlement price, fixed when it is first read after expiration
+    SettlementPrice,
+}
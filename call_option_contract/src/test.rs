@@ -2,19 +2,80 @@
 #[warn(dead_code)]
 extern crate std;
 
-use crate::oracle;
+use crate::admin::registry_address;
+use crate::{oracle, registry};
 use soroban_sdk::{
-    testutils::{Address as _, AuthorizedFunction, AuthorizedInvocation, Ledger},
-    token, Address, Env, IntoVal, Symbol,
+    symbol_short,
+    testutils::{
+        Address as _, AuthorizedFunction, AuthorizedInvocation, Events, Ledger, MockAuth,
+        MockAuthInvoke,
+    },
+    token, vec, Address, BytesN, Env, IntoVal, String, Symbol,
 };
 
+use crate::black_scholes::{call_greeks, call_price, Inputs};
+use crate::errors::Error;
+use crate::fixed_point::{exp, ln, norm_cdf, WAD};
+use crate::storage_types::{
+    AsianConfig, BarrierConfig, BarrierType, BinaryPayout, DataKey, OptionKind, OptionState,
+    Premium, PriceSource, QuoteConfig, SettlementConfig, SpreadConfig, StrikePayment, TokenPair,
+};
+use crate::upgrade::OptionInfoV1;
 use crate::{EuropeanCallOption, EuropeanCallOptionClient};
 
+// This contract's own WASM, built by `make test`, to upgrade options to
+mod option_wasm {
+    soroban_sdk::contractimport!(
+        file = "target/wasm32-unknown-unknown/release/european_call_option_contract.wasm"
+    );
+}
+
 fn create_european_call_contract<'a>(e: &Env) -> EuropeanCallOptionClient<'a> {
-    let european_call =
-        EuropeanCallOptionClient::new(e, &e.register_contract(None, EuropeanCallOption {}));
+    EuropeanCallOptionClient::new(e, &e.register_contract(None, EuropeanCallOption {}))
+}
+
+// Deploys the option registry at the id options are built with, with a random
+// admin and no fees
+fn create_registry<'a>(e: &Env) -> registry::Client<'a> {
+    let registry_id = e.register_contract_wasm(Some(&registry_address(e)), registry::WASM);
+    let registry = registry::Client::new(e, &registry_id);
+    registry.initialize(&Address::random(e), &fee_config(e, 0, 0));
+    registry
+}
+
+// Approves the oracle and token pair so init_option accepts them
+fn allow_option(e: &Env, oracle_id: &Address, escrow_token: &Address, underlying_token: &Address) {
+    let registry = registry::Client::new(e, &registry_address(e));
+    registry.set_oracle_allowed(oracle_id, &true);
+    registry.set_pair_allowed(escrow_token, underlying_token, &true);
+}
+
+fn token_pair(escrow_token: &Address, underlying_token: &Address) -> TokenPair {
+    TokenPair {
+        escrow_token: escrow_token.clone(),
+        underlying_token: underlying_token.clone(),
+    }
+}
+
+fn fee_config(e: &Env, premium_bps: u32, exercise_bps: u32) -> registry::FeeConfig {
+    registry::FeeConfig {
+        treasury: Address::random(e),
+        premium_bps,
+        bounty_bps: 0,
+        exercise_bps,
+        keeper_bounty: 0,
+    }
+}
 
-    european_call
+fn settlement_config() -> SettlementConfig {
+    SettlementConfig {
+        exercise_window: 3600,
+        max_price_age: 300,
+        twap_window: 0,
+        min_samples: 0,
+        price_source: PriceSource::Source(0),
+        strike_payment: StrikePayment::AtPurchase,
+    }
 }
 
 fn create_token_contract<'a>(
@@ -31,24 +92,29 @@ fn create_token_contract<'a>(
 fn create_option_contract<'a>(
     e: &Env,
     seller: &Address,
+    premium_token: &Address,
     escrow_token: &Address,
     underlying_token: &Address,
-    strike_price: u32,
-    premium: u32,
-    escrow_amount: u32,
+    strike_price: i128,
+    premium: i128,
+    escrow_amount: i128,
     expiration_date: u64,
+    settlement: &SettlementConfig,
     oracle_id: &Address,
 ) -> EuropeanCallOptionClient<'a> {
     let option = create_european_call_contract(&e);
+    allow_option(e, oracle_id, escrow_token, underlying_token);
 
     option.init_option(
         seller,
         &strike_price,
         &expiration_date,
-        &premium,
-        escrow_token,
+        settlement,
+        &OptionKind::Vanilla,
+        &Premium::Fixed(premium),
+        premium_token,
+        &token_pair(escrow_token, underlying_token),
         &escrow_amount,
-        underlying_token,
         oracle_id,
     );
 
@@ -65,10 +131,12 @@ fn create_option_contract<'a>(
                         seller,
                         strike_price,
                         expiration_date,
-                        premium,
-                        escrow_token.clone(),
+                        settlement.clone(),
+                        OptionKind::Vanilla,
+                        Premium::Fixed(premium),
+                        premium_token.clone(),
+                        token_pair(escrow_token, underlying_token),
                         escrow_amount,
-                        underlying_token.clone(),
                         oracle_id.clone()
                     )
                         .into_val(e)
@@ -92,6 +160,7 @@ fn create_option_contract<'a>(
 fn test_in_case_of_strike_is_high() {
     let e = Env::default();
     e.mock_all_auths();
+    create_registry(&e);
 
     e.ledger().with_mut(|li| {
         li.timestamp = 12345;
@@ -125,7 +194,7 @@ fn test_in_case_of_strike_is_high() {
         &60,
     );
 
-    let price: i128 = 9;
+    let price: i128 = 9 * 10_i128.pow(18);
     oracle_client.add_price(
         &0,
         &oracle::Asset::Stellar(escrow_token_client.address.clone()),
@@ -136,20 +205,22 @@ fn test_in_case_of_strike_is_high() {
         &e,
         &seller,
         &escrow_token_client.address,
+        &escrow_token_client.address,
         &underlying_token_client.address,
-        10,
+        100_000_000,
         10,
         100,
-        0,
+        60,
+        &settlement_config(),
         &oracle_id,
     );
 
     // check seller's balance after deposit escrow amount
     assert_eq!(escrow_token_client.balance(&seller), 900);
     std::println!("balance check ok");
-    
+
     // Verify that authorization is required for the buyer.
-    euro_option.buy_option(&buyer);
+    euro_option.buy_option(&buyer, &100, &10);
 
     assert_eq!(
         e.auths(),
@@ -159,7 +230,7 @@ fn test_in_case_of_strike_is_high() {
                 function: AuthorizedFunction::Contract((
                     euro_option.address.clone(),
                     Symbol::new(&e, "buy_option"),
-                    (&buyer,).into_val(&e),
+                    (&buyer, 100_i128, 10_i128).into_val(&e),
                 )),
                 sub_invocations: std::vec![
                     AuthorizedInvocation {
@@ -187,22 +258,12 @@ fn test_in_case_of_strike_is_high() {
     assert_eq!(underlying_token_client.balance(&buyer), 9000);
     assert_eq!(escrow_token_client.balance(&buyer), 90);
 
-    // Check with Buyer
-    euro_option.exercise_option();
-    assert_eq!(
-        e.auths(),
-        std::vec![(
-            buyer.clone(),
-            AuthorizedInvocation {
-                function: AuthorizedFunction::Contract((
-                    euro_option.address.clone(),
-                    Symbol::new(&e, "exercise_option"),
-                    ().into_val(&e)
-                )),
-                sub_invocations: std::vec![]
-            }
-        )]
-    );
+    // Out of the money: nobody exercises, anyone settles once the window closes
+    e.ledger().with_mut(|li| {
+        li.timestamp = 12345 + 60 + 3600;
+    });
+    euro_option.expire(&buyer);
+    assert_eq!(e.auths(), std::vec![]);
 
     assert_eq!(underlying_token_client.balance(&buyer), 10000);
     assert_eq!(underlying_token_client.balance(&seller), 0);
@@ -217,6 +278,7 @@ fn test_in_case_of_strike_is_high() {
 fn test_in_case_of_strike_is_low() {
     let e = Env::default();
     e.mock_all_auths();
+    create_registry(&e);
 
     e.ledger().with_mut(|li| {
         li.timestamp = 12345;
@@ -250,7 +312,7 @@ fn test_in_case_of_strike_is_low() {
         &60,
     );
 
-    let price: i128 = 12;
+    let price: i128 = 12 * 10_i128.pow(18);
     oracle_client.add_price(
         &0,
         &oracle::Asset::Stellar(escrow_token_client.address.clone()),
@@ -261,11 +323,13 @@ fn test_in_case_of_strike_is_low() {
         &e,
         &seller,
         &escrow_token_client.address,
+        &escrow_token_client.address,
         &underlying_token_client.address,
-        10,
+        100_000_000,
         10,
         100,
-        0,
+        60,
+        &settlement_config(),
         &oracle_id,
     );
 
@@ -274,7 +338,7 @@ fn test_in_case_of_strike_is_low() {
     std::println!("balance check ok");
     // Verify that authorization is required for the buyer.
 
-    euro_option.buy_option(&buyer);
+    euro_option.buy_option(&buyer, &100, &10);
     assert_eq!(
         e.auths(),
         std::vec![(
@@ -283,7 +347,7 @@ fn test_in_case_of_strike_is_low() {
                 function: AuthorizedFunction::Contract((
                     euro_option.address.clone(),
                     Symbol::new(&e, "buy_option"),
-                    (&buyer,).into_val(&e),
+                    (&buyer, 100_i128, 10_i128).into_val(&e),
                 )),
                 sub_invocations: std::vec![
                     AuthorizedInvocation {
@@ -317,17 +381,25 @@ fn test_in_case_of_strike_is_low() {
     assert_eq!(escrow_token_client.balance(&euro_option.address), 100);
     assert_eq!(underlying_token_client.balance(&euro_option.address), 1000);
 
-    // Check with seller
-    euro_option.exercise_option();
+    // In the money at expiry: only the buyer signs the exercise
+    e.ledger().with_mut(|li| {
+        li.timestamp = 12345 + 60;
+    });
+    oracle_client.add_price(
+        &0,
+        &oracle::Asset::Stellar(escrow_token_client.address.clone()),
+        &price,
+    );
+    euro_option.exercise_option(&buyer);
     assert_eq!(
         e.auths(),
         std::vec![(
-            seller.clone(),
+            buyer.clone(),
             AuthorizedInvocation {
                 function: AuthorizedFunction::Contract((
                     euro_option.address.clone(),
                     Symbol::new(&e, "exercise_option"),
-                    ().into_val(&e)
+                    (&buyer,).into_val(&e)
                 )),
                 sub_invocations: std::vec![]
             }
@@ -348,6 +420,7 @@ fn test_in_case_of_strike_is_low() {
 fn test_withdraw() {
     let e = Env::default();
     e.mock_all_auths();
+    create_registry(&e);
 
     e.ledger().with_mut(|li| {
         li.timestamp = 12345;
@@ -381,7 +454,7 @@ fn test_withdraw() {
         &60,
     );
 
-    let price: i128 = 12;
+    let price: i128 = 12 * 10_i128.pow(18);
     oracle_client.add_price(
         &0,
         &oracle::Asset::Stellar(escrow_token_client.address.clone()),
@@ -392,11 +465,13 @@ fn test_withdraw() {
         &e,
         &seller,
         &escrow_token_client.address,
+        &escrow_token_client.address,
         &underlying_token_client.address,
-        10,
+        100_000_000,
         10,
         100,
         0,
+        &settlement_config(),
         &oracle_id,
     );
 
@@ -426,3 +501,2980 @@ fn test_withdraw() {
     assert_eq!(escrow_token_client.balance(&seller), 1000);
     assert_eq!(escrow_token_client.balance(&euro_option.address.clone()), 0);
 }
+
+struct OptionTest<'a> {
+    e: Env,
+    seller: Address,
+    buyer: Address,
+    escrow_token: token::Client<'a>,
+    underlying_token: token::Client<'a>,
+    oracle: oracle::Client<'a>,
+    registry: registry::Client<'a>,
+    option: EuropeanCallOptionClient<'a>,
+}
+
+impl<'a> OptionTest<'a> {
+    // Strike 10.0, premium 10, escrow 100, created a minute before it expires
+    // at 12345, one hour window, prices up to five minutes old
+    fn setup() -> Self {
+        Self::setup_with(Env::default(), settlement_config())
+    }
+
+    fn setup_with(e: Env, settlement: SettlementConfig) -> Self {
+        Self::setup_with_terms(e, settlement, 0, Premium::Fixed(10), OptionKind::Vanilla)
+    }
+
+    // Expires expiration_date after 12345. Quoted premiums are paid in the
+    // underlying token, fixed ones in the escrow token
+    fn setup_with_terms(
+        e: Env,
+        settlement: SettlementConfig,
+        expiration_date: u64,
+        premium: Premium,
+        kind: OptionKind,
+    ) -> Self {
+        e.mock_all_auths();
+        e.ledger().with_mut(|li| {
+            li.timestamp = 12345 - 60;
+        });
+
+        let token_admin = Address::random(&e);
+        let seller = Address::random(&e);
+        let buyer = Address::random(&e);
+
+        let (escrow_token, escrow_token_admin) = create_token_contract(&e, &token_admin);
+        let (underlying_token, underlying_token_admin) = create_token_contract(&e, &token_admin);
+        escrow_token_admin.mint(&seller, &1000);
+        escrow_token_admin.mint(&buyer, &100);
+        underlying_token_admin.mint(&buyer, &10000);
+        // Spread and binary sellers collateralize in the underlying token
+        if let OptionKind::Spread(_) | OptionKind::Binary(_) = kind {
+            underlying_token_admin.mint(&seller, &1000);
+        }
+
+        let oracle_id = e.register_contract_wasm(None, oracle::WASM);
+        let oracle = oracle::Client::new(&e, &oracle_id);
+        oracle.initialize(
+            &Address::random(&e),
+            &oracle::Asset::Stellar(Address::random(&e)),
+            &18,
+            &60,
+        );
+
+        let registry = create_registry(&e);
+        let option = create_european_call_contract(&e);
+        allow_option(
+            &e,
+            &oracle_id,
+            &escrow_token.address,
+            &underlying_token.address,
+        );
+        // init_option needs a price on every configured source. Tests publish
+        // their own prices, so the history is cleared once the option exists.
+        let sources = match &settlement.price_source {
+            PriceSource::Source(source) => vec![&e, *source],
+            PriceSource::Median(sources) => sources.clone(),
+        };
+        let asset = oracle::Asset::Stellar(escrow_token.address.clone());
+        for source in sources.iter() {
+            oracle.add_price(&source, &asset, &(10 * 10_i128.pow(18)));
+        }
+        let premium_token = match premium {
+            Premium::Fixed(_) => escrow_token.address.clone(),
+            Premium::Quoted(_) => underlying_token.address.clone(),
+        };
+        option.init_option(
+            &seller,
+            &100_000_000,
+            &(expiration_date + 60),
+            &settlement,
+            &kind,
+            &premium,
+            &premium_token,
+            &token_pair(&escrow_token.address, &underlying_token.address),
+            &100,
+            &oracle_id,
+        );
+        oracle.remove_prices(&vec![&e], &vec![&e], &None, &None);
+
+        OptionTest {
+            e,
+            seller,
+            buyer,
+            escrow_token,
+            underlying_token,
+            oracle,
+            registry,
+            option,
+        }
+    }
+
+    // Publishes a price given in whole underlying tokens per escrow token
+    fn set_price(&self, price: i128) {
+        self.set_source_price(0, price);
+    }
+
+    fn set_source_price(&self, source: u32, price: i128) {
+        self.oracle.add_price(
+            &source,
+            &oracle::Asset::Stellar(self.escrow_token.address.clone()),
+            &(price * 10_i128.pow(18)),
+        );
+    }
+
+    fn set_time(&self, timestamp: u64) {
+        self.e.ledger().with_mut(|li| {
+            li.timestamp = timestamp;
+        });
+    }
+
+    // Initializes another option contract with the fixture's seller, tokens
+    // and oracle, a strike of 10.0 and escrow 100
+    fn try_init(
+        &self,
+        option: &EuropeanCallOptionClient,
+        expiration_date: u64,
+        settlement: SettlementConfig,
+        kind: OptionKind,
+        premium: Premium,
+        premium_token: &Address,
+    ) -> Result<(), Error> {
+        let tokens = token_pair(&self.escrow_token.address, &self.underlying_token.address);
+        match option.try_init_option(
+            &self.seller,
+            &100_000_000,
+            &expiration_date,
+            &settlement,
+            &kind,
+            &premium,
+            premium_token,
+            &tokens,
+            &100,
+            &self.oracle.address,
+        ) {
+            Ok(_) => Ok(()),
+            Err(Ok(error)) => Err(error),
+            Err(Err(error)) => panic!("init_option failed: {:?}", error),
+        }
+    }
+}
+
+#[test]
+fn test_exercise_in_window() {
+    let test = OptionTest::setup();
+    test.option.buy_option(&test.buyer, &100, &10);
+    test.set_time(12345);
+    test.set_price(12);
+
+    // The price observed at expiration still settles the end of the window
+    test.set_time(12345 + 3599);
+    test.option.exercise_option(&test.buyer);
+
+    assert_eq!(test.underlying_token.balance(&test.seller), 1000);
+    assert_eq!(test.escrow_token.balance(&test.buyer), 190);
+    assert_eq!(test.escrow_token.balance(&test.option.address), 0);
+    assert_eq!(test.underlying_token.balance(&test.option.address), 0);
+}
+
+#[test]
+fn test_exercise_after_window() {
+    let test = OptionTest::setup();
+    test.option.buy_option(&test.buyer, &100, &10);
+    test.set_time(12345 + 3600);
+    test.set_price(12);
+
+    let result = test.option.try_exercise_option(&test.buyer);
+    assert_eq!(result, Err(Ok(Error::ExerciseWindowClosed)));
+}
+
+#[test]
+fn test_exercise_before_expiry() {
+    let test = OptionTest::setup();
+    test.option.buy_option(&test.buyer, &100, &10);
+    test.set_time(12344);
+    test.set_price(12);
+
+    let result = test.option.try_exercise_option(&test.buyer);
+    assert_eq!(result, Err(Ok(Error::NotExpired)));
+}
+
+#[test]
+fn test_exercise_out_of_the_money() {
+    let test = OptionTest::setup();
+    test.option.buy_option(&test.buyer, &100, &10);
+    test.set_time(12345);
+    test.set_price(9);
+
+    let result = test.option.try_exercise_option(&test.buyer);
+    assert_eq!(result, Err(Ok(Error::OutOfTheMoney)));
+}
+
+#[test]
+fn test_expire_during_window() {
+    let test = OptionTest::setup();
+    test.option.buy_option(&test.buyer, &100, &10);
+    test.set_time(12345 + 3599);
+
+    let result = test.option.try_expire(&test.buyer);
+    assert_eq!(result, Err(Ok(Error::ExerciseWindowOpen)));
+}
+
+#[test]
+fn test_expire_unexercised_in_the_money() {
+    let test = OptionTest::setup();
+    test.option.buy_option(&test.buyer, &100, &10);
+    test.set_price(12);
+    test.set_time(12345 + 3600);
+
+    // The buyer let the window pass, so collateral goes back as if unexercised
+    test.option.expire(&test.buyer);
+    assert_eq!(test.e.auths(), std::vec![]);
+
+    assert_eq!(test.underlying_token.balance(&test.buyer), 10000);
+    assert_eq!(test.escrow_token.balance(&test.seller), 1010);
+    assert_eq!(test.escrow_token.balance(&test.option.address), 0);
+    assert_eq!(test.underlying_token.balance(&test.option.address), 0);
+}
+
+#[test]
+fn test_decimal_normalization() {
+    let e = Env::default();
+    e.mock_all_auths();
+    create_registry(&e);
+
+    let token_admin = Address::random(&e);
+    let seller = Address::random(&e);
+    let buyer = Address::random(&e);
+
+    let (escrow_token, escrow_token_admin) = create_token_contract(&e, &token_admin);
+    let (underlying_token, underlying_token_admin) = create_token_contract(&e, &token_admin);
+    escrow_token_admin.mint(&seller, &50_000_000);
+    underlying_token_admin.mint(&buyer, &1_000_000_000);
+    escrow_token_admin.mint(&buyer, &1);
+
+    // Oracle quotes with 2 decimals, coarser than the strike's 7
+    let oracle_id = e.register_contract_wasm(None, oracle::WASM);
+    let oracle_client = oracle::Client::new(&e, &oracle_id);
+    oracle_client.initialize(
+        &Address::random(&e),
+        &oracle::Asset::Stellar(Address::random(&e)),
+        &2,
+        &60,
+    );
+
+    oracle_client.add_price(
+        &0,
+        &oracle::Asset::Stellar(escrow_token.address.clone()),
+        &130,
+    );
+
+    // 5 escrow tokens at a strike of 1.2345678
+    let option = create_european_call_contract(&e);
+    allow_option(
+        &e,
+        &oracle_id,
+        &escrow_token.address,
+        &underlying_token.address,
+    );
+    option.init_option(
+        &seller,
+        &12_345_678,
+        &60,
+        &settlement_config(),
+        &OptionKind::Vanilla,
+        &Premium::Fixed(1),
+        &escrow_token.address,
+        &token_pair(&escrow_token.address, &underlying_token.address),
+        &50_000_000,
+        &oracle_id,
+    );
+    option.buy_option(&buyer, &50_000_000, &10);
+
+    // 5 * 1.2345678 = 6.172839 underlying tokens
+    assert_eq!(underlying_token.balance(&option.address), 61_728_390);
+
+    // 1.23 is below the strike even though it truncates to the same cents
+    oracle_client.add_price(
+        &0,
+        &oracle::Asset::Stellar(escrow_token.address.clone()),
+        &123,
+    );
+    assert_eq!(option.intrinsic_value(), 0);
+
+    e.ledger().with_mut(|li| {
+        li.timestamp += 60;
+    });
+    oracle_client.add_price(
+        &0,
+        &oracle::Asset::Stellar(escrow_token.address.clone()),
+        &124,
+    );
+    option.exercise_option(&buyer);
+    assert_eq!(underlying_token.balance(&seller), 61_728_390);
+    assert_eq!(escrow_token.balance(&buyer), 50_000_000);
+}
+
+#[test]
+fn test_deposit_overflow() {
+    let e = Env::default();
+    e.mock_all_auths();
+    create_registry(&e);
+
+    let token_admin = Address::random(&e);
+    let seller = Address::random(&e);
+    let (escrow_token, escrow_token_admin) = create_token_contract(&e, &token_admin);
+    let (underlying_token, _) = create_token_contract(&e, &token_admin);
+    let escrow_amount = i128::MAX / 2;
+    escrow_token_admin.mint(&seller, &escrow_amount);
+
+    let oracle_id = e.register_contract_wasm(None, oracle::WASM);
+    oracle::Client::new(&e, &oracle_id).initialize(
+        &Address::random(&e),
+        &oracle::Asset::Stellar(Address::random(&e)),
+        &18,
+        &60,
+    );
+
+    let option = create_european_call_contract(&e);
+    allow_option(
+        &e,
+        &oracle_id,
+        &escrow_token.address,
+        &underlying_token.address,
+    );
+    let result = option.try_init_option(
+        &seller,
+        &100_000_000,
+        &0,
+        &settlement_config(),
+        &OptionKind::Vanilla,
+        &Premium::Fixed(10),
+        &escrow_token.address,
+        &token_pair(&escrow_token.address, &underlying_token.address),
+        &escrow_amount,
+        &oracle_id,
+    );
+    assert_eq!(result, Err(Ok(Error::ArithmeticOverflow)));
+}
+
+#[test]
+fn test_premium_in_separate_token() {
+    let e = Env::default();
+    e.mock_all_auths();
+    create_registry(&e);
+
+    let token_admin = Address::random(&e);
+    let seller = Address::random(&e);
+    let buyer = Address::random(&e);
+
+    let (premium_token, premium_token_admin) = create_token_contract(&e, &token_admin);
+    let (escrow_token, escrow_token_admin) = create_token_contract(&e, &token_admin);
+    let (underlying_token, underlying_token_admin) = create_token_contract(&e, &token_admin);
+    escrow_token_admin.mint(&seller, &100);
+    premium_token_admin.mint(&buyer, &25);
+    underlying_token_admin.mint(&buyer, &1000);
+
+    let oracle_id = e.register_contract_wasm(None, oracle::WASM);
+    let oracle_client = oracle::Client::new(&e, &oracle_id);
+    oracle_client.initialize(
+        &Address::random(&e),
+        &oracle::Asset::Stellar(Address::random(&e)),
+        &18,
+        &60,
+    );
+    oracle_client.add_price(
+        &0,
+        &oracle::Asset::Stellar(escrow_token.address.clone()),
+        &(10 * 10_i128.pow(18)),
+    );
+
+    let option = create_option_contract(
+        &e,
+        &seller,
+        &premium_token.address,
+        &escrow_token.address,
+        &underlying_token.address,
+        100_000_000,
+        25,
+        100,
+        60,
+        &settlement_config(),
+        &oracle_id,
+    );
+    option.buy_option(&buyer, &100, &25);
+
+    assert_eq!(premium_token.balance(&buyer), 0);
+    assert_eq!(premium_token.balance(&seller), 25);
+    assert_eq!(escrow_token.balance(&seller), 0);
+    assert_eq!(escrow_token.balance(&option.address), 100);
+    assert_eq!(underlying_token.balance(&option.address), 1000);
+}
+
+#[test]
+fn test_exercise_without_price() {
+    let test = OptionTest::setup();
+    test.option.buy_option(&test.buyer, &100, &10);
+    test.set_time(12345);
+
+    let result = test.option.try_exercise_option(&test.buyer);
+    assert_eq!(result, Err(Ok(Error::PriceUnavailable)));
+}
+
+#[test]
+fn test_exercise_with_price_before_expiry() {
+    let test = OptionTest::setup();
+    test.option.buy_option(&test.buyer, &100, &10);
+    test.set_time(12344);
+    test.set_price(12);
+    test.set_time(12345);
+
+    let result = test.option.try_exercise_option(&test.buyer);
+    assert_eq!(result, Err(Ok(Error::PriceBeforeExpiry)));
+}
+
+#[test]
+fn test_exercise_with_stale_price() {
+    let test = OptionTest::setup();
+    test.option.buy_option(&test.buyer, &100, &10);
+    test.set_time(12345 + 301);
+    test.set_price(12);
+
+    // Published more than max_price_age after expiration
+    let result = test.option.try_exercise_option(&test.buyer);
+    assert_eq!(result, Err(Ok(Error::StalePrice)));
+
+    let test = OptionTest::setup();
+    test.option.buy_option(&test.buyer, &100, &10);
+    test.set_time(12345 + 300);
+    test.set_price(12);
+    test.option.exercise_option(&test.buyer);
+}
+
+#[test]
+fn test_settlement_price_fixed() {
+    let test = OptionTest::setup();
+    let holder = Address::random(&test.e);
+    test.option.buy_option(&test.buyer, &100, &10);
+    test.option.transfer(&test.buyer, &holder, &50);
+    assert_eq!(test.option.try_fix_price(), Err(Ok(Error::NotExpired)));
+
+    test.set_time(12345);
+    test.set_price(12);
+    test.set_time(12345 + 60);
+    test.set_price(8);
+    assert_eq!(test.option.settlement_price(), None);
+    assert_eq!(test.option.fix_price(), 12 * 10_i128.pow(18));
+    assert_eq!(test.option.settlement_price(), Some(12 * 10_i128.pow(18)));
+
+    // Kept after the oracle dropped the price from its history
+    for _ in 0..10 {
+        test.set_time(test.e.ledger().timestamp() + 60);
+        test.set_price(8);
+    }
+    test.option.exercise_option(&test.buyer);
+    test.option.exercise_option(&holder);
+    assert_eq!(test.escrow_token.balance(&holder), 50);
+    assert_eq!(test.option.state(), OptionState::Exercised);
+}
+
+#[test]
+fn test_exercise_with_non_positive_price() {
+    let test = OptionTest::setup();
+    test.option.buy_option(&test.buyer, &100, &10);
+    test.set_time(12345);
+    test.set_price(0);
+
+    let result = test.option.try_exercise_option(&test.buyer);
+    assert_eq!(result, Err(Ok(Error::InvalidPrice)));
+
+    test.set_price(-1);
+    let result = test.option.try_exercise_option(&test.buyer);
+    assert_eq!(result, Err(Ok(Error::InvalidPrice)));
+}
+
+fn twap_config() -> SettlementConfig {
+    SettlementConfig {
+        exercise_window: 3600,
+        max_price_age: 300,
+        twap_window: 300,
+        min_samples: 3,
+        price_source: PriceSource::Source(0),
+        strike_payment: StrikePayment::AtPurchase,
+    }
+}
+
+#[test]
+fn test_exercise_on_twap() {
+    let test = OptionTest::setup_with(Env::default(), twap_config());
+    test.option.buy_option(&test.buyer, &100, &10);
+
+    // Window is [12045, 12345]: 9 for 100s, 11 for 100s, 13 for 100s
+    test.set_time(12045);
+    test.set_price(9);
+    test.set_time(12145);
+    test.set_price(11);
+    test.set_time(12245);
+    test.set_price(13);
+    // Outside the window
+    test.set_time(12346);
+    test.set_price(5);
+
+    test.option.exercise_option(&test.buyer);
+    assert_eq!(test.escrow_token.balance(&test.buyer), 190);
+}
+
+#[test]
+fn test_twap_fixed_after_history() {
+    let test = OptionTest::setup_with(Env::default(), twap_config());
+    let holder = Address::random(&test.e);
+    test.option.buy_option(&test.buyer, &100, &10);
+    test.option.transfer(&test.buyer, &holder, &50);
+    test.set_time(12045);
+    test.set_price(9);
+    test.set_time(12145);
+    test.set_price(11);
+    test.set_time(12245);
+    test.set_price(13);
+
+    // The first exercise fixes the average
+    test.set_time(12345);
+    test.option.exercise_option(&holder);
+    assert_eq!(test.option.settlement_price(), Some(11 * 10_i128.pow(18)));
+
+    // Ten new prices push the window out of the oracle history
+    for _ in 0..10 {
+        test.set_time(test.e.ledger().timestamp() + 60);
+        test.set_price(5);
+    }
+    test.option.exercise_option(&test.buyer);
+    assert_eq!(test.escrow_token.balance(&test.buyer), 140);
+    assert_eq!(test.option.state(), OptionState::Exercised);
+}
+
+#[test]
+fn test_twap_ignores_spike_at_expiry() {
+    let test = OptionTest::setup_with(Env::default(), twap_config());
+    test.option.buy_option(&test.buyer, &100, &10);
+
+    // Last price is above the strike but it carries no weight in the average
+    test.set_time(12045);
+    test.set_price(8);
+    test.set_time(12145);
+    test.set_price(8);
+    test.set_time(12335);
+    test.set_price(8);
+    test.set_time(12345);
+    test.set_price(100);
+
+    let result = test.option.try_exercise_option(&test.buyer);
+    assert_eq!(result, Err(Ok(Error::OutOfTheMoney)));
+}
+
+#[test]
+fn test_twap_insufficient_samples() {
+    let test = OptionTest::setup_with(Env::default(), twap_config());
+    test.option.buy_option(&test.buyer, &100, &10);
+
+    test.set_time(12044);
+    test.set_price(12);
+    test.set_time(12145);
+    test.set_price(12);
+    test.set_time(12245);
+    test.set_price(12);
+    test.set_time(12345);
+
+    // The first price is just outside the window
+    let result = test.option.try_exercise_option(&test.buyer);
+    assert_eq!(result, Err(Ok(Error::InsufficientSamples)));
+}
+
+#[test]
+fn test_twap_samples_without_window() {
+    let test = OptionTest::setup();
+    test.set_price(10);
+    let option = create_european_call_contract(&test.e);
+    let mut settlement = twap_config();
+    settlement.twap_window = 0;
+    let result = test.try_init(
+        &option,
+        0,
+        settlement,
+        OptionKind::Vanilla,
+        Premium::Fixed(10),
+        &test.escrow_token.address,
+    );
+    assert_eq!(result, Err(Error::InvalidSettlement));
+}
+
+#[test]
+fn test_exercise_on_configured_source() {
+    let mut settlement = settlement_config();
+    settlement.price_source = PriceSource::Source(2);
+    let test = OptionTest::setup_with(Env::default(), settlement);
+    test.option.buy_option(&test.buyer, &100, &10);
+
+    test.set_time(12345);
+    // Source 0 is out of the money but the option follows source 2
+    test.set_source_price(0, 9);
+    test.set_source_price(2, 11);
+    test.option.exercise_option(&test.buyer);
+    assert_eq!(test.escrow_token.balance(&test.buyer), 190);
+}
+
+#[test]
+fn test_exercise_on_median_of_sources() {
+    let e = Env::default();
+    let mut settlement = settlement_config();
+    settlement.price_source = PriceSource::Median(soroban_sdk::vec![&e, 0, 1, 2]);
+    let test = OptionTest::setup_with(e, settlement);
+    test.option.buy_option(&test.buyer, &100, &10);
+
+    test.set_time(12345);
+    // One outlier source cannot move the median above the strike
+    test.set_source_price(0, 9);
+    test.set_source_price(1, 1000);
+    test.set_source_price(2, 8);
+    let result = test.option.try_exercise_option(&test.buyer);
+    assert_eq!(result, Err(Ok(Error::OutOfTheMoney)));
+}
+
+#[test]
+fn test_exercise_on_median_above_strike() {
+    let e = Env::default();
+    let mut settlement = settlement_config();
+    settlement.price_source = PriceSource::Median(soroban_sdk::vec![&e, 0, 1, 2]);
+    let test = OptionTest::setup_with(e, settlement);
+    test.option.buy_option(&test.buyer, &100, &10);
+
+    test.set_time(12345);
+    test.set_source_price(0, 9);
+    test.set_source_price(1, 1000);
+    test.set_source_price(2, 11);
+    test.option.exercise_option(&test.buyer);
+    assert_eq!(test.escrow_token.balance(&test.buyer), 190);
+}
+
+#[test]
+fn test_median_source_missing_price() {
+    let e = Env::default();
+    let mut settlement = settlement_config();
+    settlement.price_source = PriceSource::Median(soroban_sdk::vec![&e, 0, 1]);
+    let test = OptionTest::setup_with(e, settlement);
+    test.option.buy_option(&test.buyer, &100, &10);
+    test.set_time(12345);
+    test.set_source_price(0, 12);
+
+    let result = test.option.try_exercise_option(&test.buyer);
+    assert_eq!(result, Err(Ok(Error::PriceUnavailable)));
+}
+
+#[test]
+fn test_init_twice() {
+    let test = OptionTest::setup();
+    let result = test.try_init(
+        &test.option,
+        0,
+        settlement_config(),
+        OptionKind::Vanilla,
+        Premium::Fixed(10),
+        &test.escrow_token.address,
+    );
+    assert_eq!(result, Err(Error::AlreadyInitialized));
+}
+
+#[test]
+fn test_init_invalid_amounts() {
+    let e = Env::default();
+    e.mock_all_auths();
+    create_registry(&e);
+    let token_admin = Address::random(&e);
+    let seller = Address::random(&e);
+    let (token, _) = create_token_contract(&e, &token_admin);
+    let oracle_id = e.register_contract_wasm(None, oracle::WASM);
+    oracle::Client::new(&e, &oracle_id).initialize(
+        &Address::random(&e),
+        &oracle::Asset::Stellar(Address::random(&e)),
+        &18,
+        &60,
+    );
+    let option = create_european_call_contract(&e);
+    allow_option(&e, &oracle_id, &token.address, &token.address);
+
+    // (strike, premium, escrow amount)
+    for (strike_price, premium, escrow_amount) in [
+        (0, 10, 100),
+        (100_000_000, -1, 100),
+        (100_000_000, 10, 0),
+        (1, 10, 1),
+    ] {
+        let result = option.try_init_option(
+            &seller,
+            &strike_price,
+            &0,
+            &settlement_config(),
+            &OptionKind::Vanilla,
+            &Premium::Fixed(premium),
+            &token.address,
+            &token_pair(&token.address, &token.address),
+            &escrow_amount,
+            &oracle_id,
+        );
+        assert_eq!(result, Err(Ok(Error::InvalidAmount)));
+    }
+}
+
+#[test]
+fn test_not_initialized() {
+    let e = Env::default();
+    e.mock_all_auths();
+    let option = create_european_call_contract(&e);
+
+    let buyer = Address::random(&e);
+    assert_eq!(
+        option.try_buy_option(&buyer, &100, &10),
+        Err(Ok(Error::NotInitialized))
+    );
+    assert_eq!(
+        option.try_exercise_option(&buyer),
+        Err(Ok(Error::NotInitialized))
+    );
+    assert_eq!(option.try_expire(&buyer), Err(Ok(Error::NotInitialized)));
+    assert_eq!(option.try_withdraw(), Err(Ok(Error::NotInitialized)));
+}
+
+#[test]
+fn test_settle_without_buyer() {
+    let test = OptionTest::setup();
+    test.set_price(12);
+    assert_eq!(
+        test.option.try_exercise_option(&test.buyer),
+        Err(Ok(Error::NotPurchased))
+    );
+    assert_eq!(
+        test.option.try_expire(&test.buyer),
+        Err(Ok(Error::NotPurchased))
+    );
+}
+
+#[test]
+fn test_withdraw_after_purchase() {
+    let test = OptionTest::setup();
+    test.option.buy_option(&test.buyer, &100, &10);
+    assert_eq!(
+        test.option.try_withdraw(),
+        Err(Ok(Error::InsufficientUnits))
+    );
+}
+
+#[test]
+fn test_buy_at_expiry() {
+    let test = OptionTest::setup();
+    test.option.buy_option(&test.buyer, &40, &10);
+
+    // Unsold units can no longer be bought once the option expires
+    test.set_time(12345);
+    assert_eq!(
+        test.option.try_buy_option(&test.buyer, &60, &10),
+        Err(Ok(Error::Expired))
+    );
+    test.set_time(12345 + 3600);
+    assert_eq!(
+        test.option.try_buy_option(&test.buyer, &60, &10),
+        Err(Ok(Error::Expired))
+    );
+    assert_eq!(test.option.units_available(), 60);
+}
+
+#[test]
+fn test_lifecycle_exercised() {
+    let test = OptionTest::setup();
+    assert_eq!(test.option.state(), OptionState::Open);
+    assert_eq!(
+        test.option.try_expire(&test.buyer),
+        Err(Ok(Error::NotPurchased))
+    );
+
+    test.option.buy_option(&test.buyer, &100, &10);
+    assert_eq!(test.option.state(), OptionState::Purchased);
+    assert_eq!(
+        test.option.try_withdraw(),
+        Err(Ok(Error::InsufficientUnits))
+    );
+
+    test.set_time(12345);
+    test.set_price(12);
+    test.option.exercise_option(&test.buyer);
+    assert_eq!(test.option.state(), OptionState::Exercised);
+
+    // Terminal options stay readable but cannot move again
+    test.set_time(12345 + 3600);
+    assert_eq!(
+        test.option.try_exercise_option(&test.buyer),
+        Err(Ok(Error::OptionClosed))
+    );
+    assert_eq!(
+        test.option.try_expire(&test.buyer),
+        Err(Ok(Error::OptionClosed))
+    );
+    assert_eq!(
+        test.option.try_buy_option(&test.buyer, &100, &10),
+        Err(Ok(Error::OptionClosed))
+    );
+    test.e.as_contract(&test.option.address, || {
+        let storage = test.e.storage().instance();
+        assert!(storage.has(&DataKey::OptionInfo));
+        assert_eq!(storage.get::<_, i128>(&DataKey::Outstanding), Some(0));
+    });
+    assert_eq!(test.option.units_bought(&test.buyer), 100);
+}
+
+#[test]
+fn test_lifecycle_expired() {
+    let test = OptionTest::setup();
+    test.option.buy_option(&test.buyer, &100, &10);
+    test.set_time(12345 + 3600);
+    test.option.expire(&test.buyer);
+    assert_eq!(test.option.state(), OptionState::Expired);
+    assert_eq!(
+        test.option.try_expire(&test.buyer),
+        Err(Ok(Error::OptionClosed))
+    );
+    assert_eq!(test.option.try_withdraw(), Err(Ok(Error::OptionClosed)));
+}
+
+#[test]
+fn test_lifecycle_cancelled() {
+    let test = OptionTest::setup();
+    test.option.withdraw();
+    assert_eq!(test.option.state(), OptionState::Cancelled);
+    assert_eq!(test.option.try_withdraw(), Err(Ok(Error::OptionClosed)));
+    assert_eq!(
+        test.option.try_buy_option(&test.buyer, &100, &10),
+        Err(Ok(Error::OptionClosed))
+    );
+    assert_eq!(test.escrow_token.balance(&test.seller), 1000);
+}
+
+#[test]
+fn test_views() {
+    let test = OptionTest::setup();
+    let option = test.option.get_option();
+    assert_eq!(option.seller, test.seller);
+    assert_eq!(option.strike_price, 100_000_000);
+    assert_eq!(option.escrow_amount, 100);
+    assert_eq!(option.premium, Premium::Fixed(10));
+    assert_eq!(option.settlement, settlement_config());
+    assert_eq!(option.oracle_decimals, 18);
+    assert_eq!(option.escrow_decimals, 7);
+    assert_eq!(option.underlying_decimals, 7);
+
+    assert_eq!(test.option.init_time(), 12345 - 60);
+    assert_eq!(test.option.expires_at(), 12345);
+    assert_eq!(test.option.time_to_expiry(), 60);
+    assert_eq!(test.option.units_available(), 100);
+    assert_eq!(test.option.units_bought(&test.buyer), 0);
+
+    test.option.buy_option(&test.buyer, &100, &10);
+    assert_eq!(test.option.units_available(), 0);
+    assert_eq!(test.option.units_bought(&test.buyer), 100);
+}
+
+#[test]
+fn test_time_to_expiry() {
+    let e = Env::default();
+    e.mock_all_auths();
+    create_registry(&e);
+    let token_admin = Address::random(&e);
+    let seller = Address::random(&e);
+    let (token, token_admin_client) = create_token_contract(&e, &token_admin);
+    token_admin_client.mint(&seller, &100);
+    let oracle_id = e.register_contract_wasm(None, oracle::WASM);
+    let oracle_client = oracle::Client::new(&e, &oracle_id);
+    oracle_client.initialize(
+        &Address::random(&e),
+        &oracle::Asset::Stellar(Address::random(&e)),
+        &18,
+        &60,
+    );
+    e.ledger().with_mut(|li| {
+        li.timestamp = 1000;
+    });
+    oracle_client.add_price(
+        &0,
+        &oracle::Asset::Stellar(token.address.clone()),
+        &(10 * 10_i128.pow(18)),
+    );
+    let option = create_european_call_contract(&e);
+    allow_option(&e, &oracle_id, &token.address, &token.address);
+    option.init_option(
+        &seller,
+        &100_000_000,
+        &86400,
+        &settlement_config(),
+        &OptionKind::Vanilla,
+        &Premium::Fixed(10),
+        &token.address,
+        &token_pair(&token.address, &token.address),
+        &100,
+        &oracle_id,
+    );
+
+    assert_eq!(option.expires_at(), 87400);
+    assert_eq!(option.time_to_expiry(), 86400);
+    e.ledger().with_mut(|li| {
+        li.timestamp = 87000;
+    });
+    assert_eq!(option.time_to_expiry(), 400);
+    e.ledger().with_mut(|li| {
+        li.timestamp = 90000;
+    });
+    assert_eq!(option.time_to_expiry(), 0);
+}
+
+#[test]
+fn test_is_exercisable() {
+    let test = OptionTest::setup();
+    test.set_price(12);
+    // Not purchased yet
+    assert_eq!(test.option.is_exercisable(), false);
+
+    test.option.buy_option(&test.buyer, &100, &10);
+    test.set_time(12345);
+    test.set_price(12);
+    assert_eq!(test.option.is_exercisable(), true);
+
+    // Later prices do not move the settlement price
+    test.set_time(12345 + 60);
+    test.set_price(9);
+    assert_eq!(test.option.is_exercisable(), true);
+
+    test.set_time(12345 + 3600);
+    assert_eq!(test.option.is_exercisable(), false);
+}
+
+#[test]
+fn test_intrinsic_value() {
+    let test = OptionTest::setup();
+    assert_eq!(
+        test.option.try_intrinsic_value(),
+        Err(Ok(Error::PriceUnavailable))
+    );
+
+    test.set_price(9);
+    assert_eq!(test.option.intrinsic_value(), 0);
+
+    // 100 escrow units * (12.5 - 10)
+    test.oracle.add_price(
+        &0,
+        &oracle::Asset::Stellar(test.escrow_token.address.clone()),
+        &(125 * 10_i128.pow(17)),
+    );
+    assert_eq!(test.option.intrinsic_value(), 250);
+}
+
+#[test]
+fn test_lifecycle_events() {
+    let test = OptionTest::setup();
+    let e = &test.e;
+    let option_id = test.option.address.clone();
+
+    let event = e.events().all().last().unwrap();
+    assert_eq!(
+        vec![e, event],
+        vec![
+            e,
+            (
+                option_id.clone(),
+                (
+                    symbol_short!("option"),
+                    symbol_short!("created"),
+                    test.seller.clone()
+                )
+                    .into_val(e),
+                (
+                    test.escrow_token.address.clone(),
+                    100_i128,
+                    100_000_000_i128,
+                    12345_u64
+                )
+                    .into_val(e)
+            )
+        ]
+    );
+
+    test.option.buy_option(&test.buyer, &100, &10);
+    let event = e.events().all().last().unwrap();
+    assert_eq!(
+        vec![e, event],
+        vec![
+            e,
+            (
+                option_id.clone(),
+                (
+                    symbol_short!("option"),
+                    symbol_short!("purchased"),
+                    test.buyer.clone()
+                )
+                    .into_val(e),
+                (test.escrow_token.address.clone(), 10_i128, 1000_i128).into_val(e)
+            )
+        ]
+    );
+
+    test.set_time(12345);
+    test.set_price(12);
+    test.option.exercise_option(&test.buyer);
+    let event = e.events().all().last().unwrap();
+    assert_eq!(
+        vec![e, event],
+        vec![
+            e,
+            (
+                option_id.clone(),
+                (
+                    symbol_short!("option"),
+                    symbol_short!("exercised"),
+                    test.buyer.clone()
+                )
+                    .into_val(e),
+                (
+                    12 * 10_i128.pow(18),
+                    test.escrow_token.address.clone(),
+                    100_i128,
+                    1000_i128
+                )
+                    .into_val(e)
+            )
+        ]
+    );
+}
+
+#[test]
+fn test_expired_event() {
+    let test = OptionTest::setup();
+    let e = &test.e;
+    test.option.buy_option(&test.buyer, &100, &10);
+    test.set_time(12345 + 3600);
+    test.option.expire(&test.buyer);
+
+    let event = e.events().all().last().unwrap();
+    assert_eq!(
+        vec![e, event],
+        vec![
+            e,
+            (
+                test.option.address.clone(),
+                (
+                    symbol_short!("option"),
+                    symbol_short!("expired"),
+                    test.buyer.clone()
+                )
+                    .into_val(e),
+                (1000_i128, test.escrow_token.address.clone(), 100_i128).into_val(e)
+            )
+        ]
+    );
+}
+
+#[test]
+fn test_refunded_event() {
+    let test = OptionTest::setup();
+    let e = &test.e;
+    test.option.buy_option(&test.buyer, &100, &10);
+    test.option.burn(&test.buyer, &40);
+
+    let event = e.events().all().last().unwrap();
+    assert_eq!(
+        vec![e, event],
+        vec![
+            e,
+            (
+                test.option.address.clone(),
+                (
+                    symbol_short!("option"),
+                    symbol_short!("refunded"),
+                    test.buyer.clone()
+                )
+                    .into_val(e),
+                (400_i128, test.escrow_token.address.clone(), 40_i128).into_val(e)
+            )
+        ]
+    );
+}
+
+#[test]
+fn test_withdrawn_event() {
+    let test = OptionTest::setup();
+    let e = &test.e;
+    test.option.withdraw();
+
+    let event = e.events().all().last().unwrap();
+    assert_eq!(
+        vec![e, event],
+        vec![
+            e,
+            (
+                test.option.address.clone(),
+                (
+                    symbol_short!("option"),
+                    symbol_short!("withdrawn"),
+                    test.seller.clone()
+                )
+                    .into_val(e),
+                (test.escrow_token.address.clone(), 100_i128).into_val(e)
+            )
+        ]
+    );
+}
+
+#[test]
+fn test_position_token_metadata() {
+    let test = OptionTest::setup();
+    let e = &test.e;
+    assert_eq!(test.option.decimals(), 7);
+    assert_eq!(
+        test.option.name(),
+        String::from_slice(e, "European Call Option")
+    );
+    assert_eq!(test.option.symbol(), String::from_slice(e, "CALL"));
+
+    assert_eq!(test.option.balance(&test.buyer), 0);
+    test.option.buy_option(&test.buyer, &100, &10);
+    assert_eq!(test.option.balance(&test.buyer), 100);
+    assert_eq!(test.option.balance(&test.seller), 0);
+}
+
+#[test]
+fn test_transferred_position_exercise() {
+    let test = OptionTest::setup();
+    let holder = Address::random(&test.e);
+    test.option.buy_option(&test.buyer, &100, &10);
+    test.option.transfer(&test.buyer, &holder, &100);
+    assert_eq!(test.option.balance(&test.buyer), 0);
+    assert_eq!(test.option.balance(&holder), 100);
+
+    test.set_time(12345);
+    test.set_price(12);
+    assert_eq!(
+        test.option.try_exercise_option(&test.buyer),
+        Err(Ok(Error::NoPosition))
+    );
+    test.option.exercise_option(&holder);
+    assert_eq!(test.option.state(), OptionState::Exercised);
+    assert_eq!(test.option.balance(&holder), 0);
+
+    // The holder takes the escrow, the seller the buyer's strike deposit
+    assert_eq!(test.escrow_token.balance(&holder), 100);
+    assert_eq!(test.escrow_token.balance(&test.buyer), 90);
+    assert_eq!(test.underlying_token.balance(&test.seller), 1000);
+    assert_eq!(test.underlying_token.balance(&test.buyer), 9000);
+}
+
+#[test]
+fn test_partial_position_settlement() {
+    let test = OptionTest::setup();
+    let holder = Address::random(&test.e);
+    test.option.buy_option(&test.buyer, &100, &10);
+    test.option.transfer(&test.buyer, &holder, &40);
+
+    test.set_time(12345);
+    test.set_price(12);
+    test.option.exercise_option(&holder);
+    assert_eq!(test.option.state(), OptionState::Purchased);
+    assert_eq!(test.escrow_token.balance(&holder), 40);
+    assert_eq!(test.underlying_token.balance(&test.seller), 400);
+
+    // The buyer lets the rest lapse and gets their share of the deposit back
+    test.set_time(12345 + 3600);
+    test.option.expire(&test.buyer);
+    assert_eq!(test.option.state(), OptionState::Expired);
+    assert_eq!(test.underlying_token.balance(&test.buyer), 9600);
+    assert_eq!(test.escrow_token.balance(&test.seller), 970);
+
+    assert_eq!(test.escrow_token.balance(&test.option.address), 0);
+    assert_eq!(test.underlying_token.balance(&test.option.address), 0);
+}
+
+#[test]
+fn test_position_transfer_from() {
+    let test = OptionTest::setup();
+    let spender = Address::random(&test.e);
+    let holder = Address::random(&test.e);
+    test.option.buy_option(&test.buyer, &100, &10);
+
+    let expiration_ledger = test.e.ledger().sequence() + 100;
+    test.option
+        .approve(&test.buyer, &spender, &50, &expiration_ledger);
+    assert_eq!(test.option.allowance(&test.buyer, &spender), 50);
+
+    test.option
+        .transfer_from(&spender, &test.buyer, &holder, &30);
+    assert_eq!(test.option.allowance(&test.buyer, &spender), 20);
+    assert_eq!(test.option.balance(&test.buyer), 70);
+    assert_eq!(test.option.balance(&holder), 30);
+
+    assert_eq!(
+        test.option
+            .try_transfer_from(&spender, &test.buyer, &holder, &30),
+        Err(Ok(Error::InsufficientAllowance))
+    );
+    assert_eq!(
+        test.option.try_transfer(&holder, &test.buyer, &31),
+        Err(Ok(Error::InsufficientBalance))
+    );
+    assert_eq!(
+        test.option.try_transfer(&holder, &test.buyer, &-1),
+        Err(Ok(Error::InvalidAmount))
+    );
+}
+
+#[test]
+fn test_position_burn() {
+    let test = OptionTest::setup();
+    assert_eq!(
+        test.option.try_burn(&test.buyer, &10),
+        Err(Ok(Error::NotPurchased))
+    );
+    test.option.buy_option(&test.buyer, &100, &10);
+
+    test.option.burn(&test.buyer, &40);
+    assert_eq!(test.option.state(), OptionState::Purchased);
+    assert_eq!(test.underlying_token.balance(&test.buyer), 9400);
+    assert_eq!(test.escrow_token.balance(&test.seller), 950);
+
+    assert_eq!(
+        test.option.try_burn(&test.buyer, &61),
+        Err(Ok(Error::InsufficientBalance))
+    );
+    test.option.burn(&test.buyer, &60);
+    assert_eq!(test.option.state(), OptionState::Expired);
+    assert_eq!(test.underlying_token.balance(&test.buyer), 10000);
+    assert_eq!(test.escrow_token.balance(&test.seller), 1010);
+}
+
+#[test]
+fn test_position_transfer_event() {
+    let test = OptionTest::setup();
+    let e = &test.e;
+    let holder = Address::random(e);
+    test.option.buy_option(&test.buyer, &100, &10);
+    test.option.transfer(&test.buyer, &holder, &25);
+
+    let event = e.events().all().last().unwrap();
+    assert_eq!(
+        vec![e, event],
+        vec![
+            e,
+            (
+                test.option.address.clone(),
+                (
+                    Symbol::new(e, "transfer"),
+                    test.buyer.clone(),
+                    holder.clone()
+                )
+                    .into_val(e),
+                25_i128.into_val(e)
+            )
+        ]
+    );
+}
+
+#[test]
+fn test_fractional_purchases() {
+    let test = OptionTest::setup();
+    let buyer2 = Address::random(&test.e);
+    let underlying_admin = token::AdminClient::new(&test.e, &test.underlying_token.address);
+    underlying_admin.mint(&buyer2, &10000);
+    test.escrow_token.transfer(&test.buyer, &buyer2, &50);
+
+    // 30 units cost 30% of the premium and of the strike deposit
+    test.option.buy_option(&test.buyer, &30, &10);
+    assert_eq!(test.option.state(), OptionState::Purchased);
+    assert_eq!(test.escrow_token.balance(&test.seller), 903);
+    assert_eq!(test.underlying_token.balance(&test.buyer), 9700);
+
+    test.option.buy_option(&buyer2, &45, &10);
+    assert_eq!(test.escrow_token.balance(&test.seller), 908);
+    assert_eq!(test.underlying_token.balance(&buyer2), 9550);
+    assert_eq!(test.option.units_bought(&test.buyer), 30);
+    assert_eq!(test.option.units_bought(&buyer2), 45);
+    assert_eq!(test.option.balance(&buyer2), 45);
+    assert_eq!(test.option.units_available(), 25);
+
+    assert_eq!(
+        test.option.try_buy_option(&buyer2, &26, &10),
+        Err(Ok(Error::InsufficientUnits))
+    );
+    assert_eq!(
+        test.option.try_buy_option(&buyer2, &0, &10),
+        Err(Ok(Error::InvalidAmount))
+    );
+
+    // Each buyer settles their own units
+    test.set_time(12345);
+    test.set_price(12);
+    test.option.exercise_option(&buyer2);
+    assert_eq!(test.escrow_token.balance(&buyer2), 90);
+    assert_eq!(test.underlying_token.balance(&test.seller), 450);
+    test.set_time(12345 + 3600);
+    test.option.expire(&test.buyer);
+    assert_eq!(test.underlying_token.balance(&test.buyer), 10000);
+    assert_eq!(test.escrow_token.balance(&test.seller), 938);
+    assert_eq!(test.option.state(), OptionState::Purchased);
+
+    // Only the unsold remainder goes back to the seller, which ends the series
+    test.option.withdraw();
+    assert_eq!(test.escrow_token.balance(&test.seller), 963);
+    assert_eq!(test.option.state(), OptionState::Expired);
+    assert_eq!(test.escrow_token.balance(&test.option.address), 0);
+    assert_eq!(test.underlying_token.balance(&test.option.address), 0);
+}
+
+#[test]
+fn test_withdraw_unsold_units() {
+    let test = OptionTest::setup();
+    test.option.buy_option(&test.buyer, &60, &10);
+    test.option.withdraw();
+    assert_eq!(test.escrow_token.balance(&test.seller), 906 + 40);
+    assert_eq!(test.option.units_available(), 0);
+    assert_eq!(test.option.state(), OptionState::Purchased);
+    assert_eq!(
+        test.option.try_withdraw(),
+        Err(Ok(Error::InsufficientUnits))
+    );
+    assert_eq!(
+        test.option.try_buy_option(&test.buyer, &1, &10),
+        Err(Ok(Error::InsufficientUnits))
+    );
+
+    test.set_time(12345);
+    test.set_price(12);
+    test.option.exercise_option(&test.buyer);
+    assert_eq!(test.option.state(), OptionState::Exercised);
+    assert_eq!(test.escrow_token.balance(&test.buyer), 94 + 60);
+    assert_eq!(test.underlying_token.balance(&test.seller), 600);
+}
+
+#[test]
+fn test_fractional_premium_rounds_up() {
+    let test = OptionTest::setup();
+    // 10 premium over 100 units: 1 unit owes 0.1 and pays 1
+    test.option.buy_option(&test.buyer, &1, &10);
+    assert_eq!(test.escrow_token.balance(&test.seller), 901);
+    assert_eq!(test.underlying_token.balance(&test.buyer), 9990);
+}
+
+#[test]
+fn test_second_buyer_does_not_replace_first() {
+    let test = OptionTest::setup();
+    let buyer2 = Address::random(&test.e);
+    token::AdminClient::new(&test.e, &test.underlying_token.address).mint(&buyer2, &10000);
+    test.escrow_token.transfer(&test.buyer, &buyer2, &50);
+
+    test.option.buy_option(&test.buyer, &40, &10);
+    test.option.buy_option(&buyer2, &60, &10);
+    assert_eq!(test.option.balance(&test.buyer), 40);
+    assert_eq!(test.option.balance(&buyer2), 60);
+
+    // Both buyers can still settle their own units
+    test.set_time(12345);
+    test.set_price(12);
+    test.option.exercise_option(&test.buyer);
+    test.option.exercise_option(&buyer2);
+    assert_eq!(test.option.state(), OptionState::Exercised);
+    assert_eq!(test.escrow_token.balance(&test.buyer), 46 + 40);
+    assert_eq!(test.escrow_token.balance(&buyer2), 44 + 60);
+    assert_eq!(test.underlying_token.balance(&test.seller), 1000);
+    assert_eq!(test.underlying_token.balance(&test.option.address), 0);
+}
+
+#[test]
+fn test_repeat_purchase_accumulates() {
+    let test = OptionTest::setup();
+    test.option.buy_option(&test.buyer, &30, &10);
+    test.option.buy_option(&test.buyer, &20, &10);
+    assert_eq!(test.option.units_bought(&test.buyer), 50);
+    assert_eq!(test.option.balance(&test.buyer), 50);
+    assert_eq!(test.underlying_token.balance(&test.option.address), 500);
+}
+
+#[test]
+fn test_competing_purchase_moves_no_funds() {
+    let test = OptionTest::setup();
+    let buyer2 = Address::random(&test.e);
+    token::AdminClient::new(&test.e, &test.underlying_token.address).mint(&buyer2, &10000);
+    test.escrow_token.transfer(&test.buyer, &buyer2, &50);
+
+    test.option.buy_option(&test.buyer, &70, &10);
+    assert_eq!(
+        test.option.try_buy_option(&buyer2, &40, &10),
+        Err(Ok(Error::InsufficientUnits))
+    );
+    assert_eq!(test.escrow_token.balance(&buyer2), 50);
+    assert_eq!(test.underlying_token.balance(&buyer2), 10000);
+    assert_eq!(test.option.balance(&buyer2), 0);
+    assert_eq!(test.option.units_bought(&buyer2), 0);
+
+    // The rest of the series is still for sale
+    test.option.buy_option(&buyer2, &30, &10);
+    assert_eq!(
+        test.option.try_buy_option(&test.buyer, &1, &10),
+        Err(Ok(Error::InsufficientUnits))
+    );
+    assert_eq!(test.underlying_token.balance(&test.option.address), 1000);
+}
+
+#[test]
+fn test_refund_purchase() {
+    let test = OptionTest::setup();
+    test.option.buy_option(&test.buyer, &100, &10);
+    test.option.burn(&test.buyer, &100);
+
+    // The strike deposit comes back in full, the premium stays with the seller
+    assert_eq!(test.option.state(), OptionState::Expired);
+    assert_eq!(test.underlying_token.balance(&test.buyer), 10000);
+    assert_eq!(test.escrow_token.balance(&test.buyer), 90);
+    assert_eq!(test.escrow_token.balance(&test.seller), 1010);
+    assert_eq!(test.escrow_token.balance(&test.option.address), 0);
+    assert_eq!(test.underlying_token.balance(&test.option.address), 0);
+}
+
+#[test]
+fn test_transfer_to_option_contract() {
+    let test = OptionTest::setup();
+    let spender = Address::random(&test.e);
+    test.option.buy_option(&test.buyer, &100, &10);
+    assert_eq!(
+        test.option
+            .try_transfer(&test.buyer, &test.option.address, &10),
+        Err(Ok(Error::InvalidRecipient))
+    );
+
+    let expiration_ledger = test.e.ledger().sequence() + 100;
+    test.option
+        .approve(&test.buyer, &spender, &10, &expiration_ledger);
+    assert_eq!(
+        test.option
+            .try_transfer_from(&spender, &test.buyer, &test.option.address, &10),
+        Err(Ok(Error::InvalidRecipient))
+    );
+    assert_eq!(test.option.balance(&test.buyer), 100);
+}
+
+fn pay_at_exercise_config() -> SettlementConfig {
+    SettlementConfig {
+        strike_payment: StrikePayment::AtExercise,
+        ..settlement_config()
+    }
+}
+
+#[test]
+fn test_pay_strike_at_exercise() {
+    let test = OptionTest::setup_with(Env::default(), pay_at_exercise_config());
+    test.option.buy_option(&test.buyer, &100, &10);
+
+    // Only the premium is paid at purchase
+    assert_eq!(test.escrow_token.balance(&test.seller), 910);
+    assert_eq!(test.underlying_token.balance(&test.buyer), 10000);
+    assert_eq!(test.underlying_token.balance(&test.option.address), 0);
+
+    test.set_time(12345);
+    test.set_price(12);
+    test.option.exercise_option(&test.buyer);
+    assert_eq!(test.option.state(), OptionState::Exercised);
+    assert_eq!(test.underlying_token.balance(&test.buyer), 9000);
+    assert_eq!(test.underlying_token.balance(&test.seller), 1000);
+    assert_eq!(test.escrow_token.balance(&test.buyer), 190);
+    assert_eq!(test.escrow_token.balance(&test.option.address), 0);
+}
+
+#[test]
+fn test_pay_strike_at_exercise_lapses() {
+    let test = OptionTest::setup_with(Env::default(), pay_at_exercise_config());
+    test.option.buy_option(&test.buyer, &100, &10);
+
+    // Not exercised in the window: the seller reclaims the escrow
+    test.set_time(12345 + 3600);
+    test.option.expire(&test.buyer);
+    assert_eq!(test.option.state(), OptionState::Expired);
+    assert_eq!(test.escrow_token.balance(&test.seller), 1010);
+    assert_eq!(test.underlying_token.balance(&test.buyer), 10000);
+    assert_eq!(test.escrow_token.balance(&test.option.address), 0);
+}
+
+#[test]
+fn test_pay_strike_at_exercise_partial() {
+    let test = OptionTest::setup_with(Env::default(), pay_at_exercise_config());
+    let holder = Address::random(&test.e);
+    token::AdminClient::new(&test.e, &test.underlying_token.address).mint(&holder, &300);
+    test.option.buy_option(&test.buyer, &100, &10);
+    test.option.transfer(&test.buyer, &holder, &30);
+
+    test.set_time(12345);
+    test.set_price(12);
+    test.option.exercise_option(&holder);
+    assert_eq!(test.underlying_token.balance(&holder), 0);
+    assert_eq!(test.underlying_token.balance(&test.seller), 300);
+    assert_eq!(test.escrow_token.balance(&holder), 30);
+
+    test.set_time(12345 + 3600);
+    test.option.expire(&test.buyer);
+    assert_eq!(test.option.state(), OptionState::Expired);
+    assert_eq!(test.escrow_token.balance(&test.seller), 980);
+}
+
+fn assert_close(actual: i128, expected: i128, tolerance: i128) {
+    assert!(
+        (actual - expected).abs() <= tolerance,
+        "{} is not within {} of {}",
+        actual,
+        tolerance,
+        expected
+    );
+}
+
+#[test]
+fn test_fixed_point_functions() {
+    assert_eq!(ln(WAD), Some(0));
+    assert_close(ln(2 * WAD).unwrap(), 693_147_180_559_945_309, 10);
+    assert_close(ln(WAD / 10).unwrap(), -2_302_585_092_994_045_684, 10);
+    assert_eq!(ln(0), None);
+
+    assert_eq!(exp(0), Some(WAD));
+    assert_close(exp(WAD).unwrap(), 2_718_281_828_459_045_235, 100);
+    assert_close(exp(-3 * WAD).unwrap(), 49_787_068_367_863_943, 100);
+    assert_eq!(exp(-100 * WAD), Some(0));
+    assert_eq!(exp(100 * WAD), None);
+
+    // Reference values from erfc in double precision
+    assert_eq!(norm_cdf(0), Some(WAD / 2));
+    assert_close(norm_cdf(WAD).unwrap(), 841_344_746_068_542_900, 1_000);
+    assert_close(norm_cdf(-2 * WAD).unwrap(), 22_750_131_948_179_220, 1_000);
+    assert_close(
+        norm_cdf(3 * WAD / 10).unwrap(),
+        617_911_422_188_952_600,
+        1_000,
+    );
+    assert_close(norm_cdf(-8 * WAD).unwrap(), 622, 10);
+    assert_eq!(norm_cdf(40 * WAD), Some(WAD));
+}
+
+#[test]
+fn test_black_scholes_reference_prices() {
+    // (spot / strike, years, rate, volatility, call price / strike)
+    for (moneyness, time, rate, volatility, expected) in [
+        (WAD, WAD, WAD / 20, WAD / 5, 104_505_835_721_855_650),
+        // Hull, Options, Futures and Other Derivatives: S=42, K=40
+        (
+            1_050_000_000_000_000_000,
+            WAD / 2,
+            WAD / 10,
+            WAD / 5,
+            118_985_559_821_788_375,
+        ),
+        // S=10, K=12, 30 days
+        (
+            833_333_333_333_333_333,
+            82_191_780_821_917_808,
+            3 * WAD / 100,
+            4 * WAD / 5,
+            25_745_431_696_221_936,
+        ),
+    ] {
+        let inputs = Inputs {
+            moneyness,
+            time,
+            rate,
+            volatility,
+        };
+        assert_close(call_price(&inputs).unwrap(), expected, 1_000_000);
+    }
+
+    // At expiry the price is the intrinsic value
+    let inputs = Inputs {
+        moneyness: 1_200_000_000_000_000_000,
+        time: 0,
+        rate: WAD / 20,
+        volatility: WAD / 5,
+    };
+    assert_eq!(call_price(&inputs), Some(WAD / 5));
+}
+
+fn quote_config() -> QuoteConfig {
+    QuoteConfig {
+        volatility: 2_000_000,
+        risk_free_rate: 500_000,
+    }
+}
+
+#[test]
+fn test_quote_premium() {
+    // One year to expiry, at the money
+    let test = OptionTest::setup_with_terms(
+        Env::default(),
+        settlement_config(),
+        31_536_000,
+        Premium::Quoted(quote_config()),
+        OptionKind::Vanilla,
+    );
+    test.set_price(10);
+
+    // 100 units at 1.0450583 underlying tokens each
+    assert_eq!(test.option.quote_premium(&100, &2_000_000, &500_000), 104);
+    assert_eq!(test.option.quote_premium(&100, &0, &0), 0);
+    assert_eq!(
+        test.option.try_quote_premium(&100, &-1, &500_000),
+        Err(Ok(Error::InvalidAmount))
+    );
+
+    // Once expired the quote is the intrinsic value
+    test.set_time(12345 + 31_536_000);
+    test.set_price(12);
+    assert_eq!(test.option.quote_premium(&100, &2_000_000, &500_000), 200);
+}
+
+#[test]
+fn test_buy_at_quoted_premium() {
+    let test = OptionTest::setup_with_terms(
+        Env::default(),
+        settlement_config(),
+        31_536_000,
+        Premium::Quoted(quote_config()),
+        OptionKind::Vanilla,
+    );
+    test.set_price(10);
+
+    assert_eq!(
+        test.option.try_buy_option(&test.buyer, &100, &103),
+        Err(Ok(Error::PremiumAboveLimit))
+    );
+    test.option.buy_option(&test.buyer, &100, &104);
+    assert_eq!(test.underlying_token.balance(&test.seller), 104);
+    assert_eq!(
+        test.underlying_token.balance(&test.buyer),
+        10000 - 104 - 1000
+    );
+    assert_eq!(test.escrow_token.balance(&test.buyer), 100);
+}
+
+#[test]
+fn test_buy_at_stale_quote() {
+    let test = OptionTest::setup_with_terms(
+        Env::default(),
+        settlement_config(),
+        31_536_000,
+        Premium::Quoted(quote_config()),
+        OptionKind::Vanilla,
+    );
+    test.set_price(10);
+
+    // Quotes follow the settlement's max_price_age
+    test.set_time(12345 - 60 + 301);
+    assert_eq!(
+        test.option.try_quote_premium(&100, &2_000_000, &500_000),
+        Err(Ok(Error::StalePrice))
+    );
+    assert_eq!(
+        test.option.try_buy_option(&test.buyer, &100, &1000),
+        Err(Ok(Error::StalePrice))
+    );
+
+    test.set_price(10);
+    test.option.buy_option(&test.buyer, &100, &1000);
+    assert_eq!(test.option.balance(&test.buyer), 100);
+}
+
+#[test]
+fn test_fixed_premium_limit() {
+    let test = OptionTest::setup();
+    assert_eq!(
+        test.option.try_buy_option(&test.buyer, &100, &9),
+        Err(Ok(Error::PremiumAboveLimit))
+    );
+    assert_eq!(test.escrow_token.balance(&test.buyer), 100);
+}
+
+#[test]
+fn test_quoted_premium_token() {
+    let test = OptionTest::setup();
+    let option = create_european_call_contract(&test.e);
+    let result = test.try_init(
+        &option,
+        0,
+        settlement_config(),
+        OptionKind::Vanilla,
+        Premium::Quoted(quote_config()),
+        &test.escrow_token.address,
+    );
+    assert_eq!(result, Err(Error::InvalidPremiumToken));
+}
+
+#[test]
+fn test_black_scholes_reference_greeks() {
+    // Hull, Options, Futures and Other Derivatives: S=42, K=40, normalized by
+    // the strike
+    let inputs = Inputs {
+        moneyness: 1_050_000_000_000_000_000,
+        time: WAD / 2,
+        rate: WAD / 10,
+        volatility: WAD / 5,
+    };
+    let greeks = call_greeks(&inputs).unwrap();
+    assert_close(greeks.delta, 779_131_290_942_668_900, 10_000_000);
+    assert_close(greeks.gamma / 40, 49_962_670_405_911_860, 10_000_000);
+    assert_close(greeks.vega * 40, 8_813_415_059_602_853_000, 10_000_000);
+    assert_close(greeks.theta * 40, -4_559_092_194_592_626_000, 10_000_000);
+    assert_close(greeks.rho * 40, 13_982_045_913_360_282_000, 10_000_000);
+
+    // At expiry in the money only the discounting is left
+    let inputs = Inputs {
+        moneyness: 1_200_000_000_000_000_000,
+        time: 0,
+        rate: WAD / 20,
+        volatility: WAD / 5,
+    };
+    let greeks = call_greeks(&inputs).unwrap();
+    assert_eq!(greeks.delta, WAD);
+    assert_eq!(greeks.gamma, 0);
+    assert_eq!(greeks.vega, 0);
+    assert_eq!(greeks.theta, -WAD / 20);
+    assert_eq!(greeks.rho, 0);
+}
+
+#[test]
+fn test_greeks() {
+    // One year to expiry, strike and spot 10.0, volatility 20%, rate 5%
+    let test = OptionTest::setup_with_terms(
+        Env::default(),
+        settlement_config(),
+        31_536_000,
+        Premium::Fixed(10),
+        OptionKind::Vanilla,
+    );
+    test.set_time(12345);
+    test.set_price(10);
+
+    let greeks = test.option.greeks(&2_000_000, &500_000);
+    assert_close(greeks.delta, 636_830_651_175_619_100, 10_000_000);
+    assert_close(greeks.gamma, 187_620_173_458_468_950, 10_000_000);
+    assert_close(greeks.vega, 3_752_403_469_169_379_000, 10_000_000);
+    assert_close(greeks.theta, -641_402_754_643_819_700, 10_000_000);
+    assert_close(greeks.rho, 5_323_248_154_537_634_500, 10_000_000);
+
+    assert_eq!(
+        test.option.try_greeks(&-1, &500_000),
+        Err(Ok(Error::InvalidAmount))
+    );
+}
+
+#[test]
+fn test_protocol_fees() {
+    let test = OptionTest::setup();
+    let fees = fee_config(&test.e, 1000, 500);
+    test.registry.set_fees(&fees);
+    assert_eq!(test.registry.fees(), fees);
+
+    // 10% of the premium goes to the treasury
+    test.option.buy_option(&test.buyer, &100, &10);
+    assert_eq!(test.escrow_token.balance(&test.seller), 909);
+    assert_eq!(test.escrow_token.balance(&fees.treasury), 1);
+    assert_eq!(test.escrow_token.balance(&test.buyer), 90);
+
+    // 5% of the escrow delivered at exercise goes to the treasury
+    test.set_time(12345);
+    test.set_price(12);
+    test.option.exercise_option(&test.buyer);
+    assert_eq!(test.escrow_token.balance(&test.buyer), 185);
+    assert_eq!(test.escrow_token.balance(&fees.treasury), 6);
+    assert_eq!(test.underlying_token.balance(&test.seller), 1000);
+    assert_eq!(test.escrow_token.balance(&test.option.address), 0);
+}
+
+#[test]
+fn test_fee_funds_bounty_pool() {
+    let test = OptionTest::setup_with_terms(
+        Env::default(),
+        settlement_config(),
+        0,
+        Premium::Fixed(100),
+        OptionKind::Vanilla,
+    );
+    let mut fees = fee_config(&test.e, 1000, 0);
+    fees.bounty_bps = 4000;
+    fees.keeper_bounty = 3;
+    test.registry.set_fees(&fees);
+
+    // 40% of the 10% fee goes to the bounty pool, the rest to the treasury
+    test.option.buy_option(&test.buyer, &100, &100);
+    assert_eq!(test.escrow_token.balance(&test.seller), 990);
+    assert_eq!(test.escrow_token.balance(&fees.treasury), 6);
+    assert_eq!(test.option.bounty_pool(), 4);
+    // Held along with the escrow
+    assert_eq!(test.escrow_token.balance(&test.option.address), 104);
+
+    // Keepers are paid out of it
+    let keeper = Address::random(&test.e);
+    test.set_time(12345 + 3600);
+    test.set_price(8);
+    assert_eq!(test.option.settle(&keeper, &0, &10), 1);
+    assert_eq!(test.escrow_token.balance(&keeper), 3);
+
+    // What keepers did not claim goes back to the seller with the escrow
+    assert_eq!(test.option.bounty_pool(), 0);
+    assert_eq!(test.escrow_token.balance(&test.seller), 1091);
+    assert_eq!(test.escrow_token.balance(&test.option.address), 0);
+}
+
+#[test]
+fn test_fee_event() {
+    let test = OptionTest::setup();
+    let e = &test.e;
+    let fees = fee_config(e, 1000, 0);
+    test.registry.set_fees(&fees);
+    test.option.buy_option(&test.buyer, &100, &10);
+
+    // The fee is published before the mint and purchase events
+    let events: std::vec::Vec<_> = e
+        .events()
+        .all()
+        .iter()
+        .filter(|event| event.0 == test.option.address)
+        .collect();
+    let event = events[events.len() - 3].clone();
+    assert_eq!(
+        vec![e, event],
+        vec![
+            e,
+            (
+                test.option.address.clone(),
+                (
+                    symbol_short!("option"),
+                    symbol_short!("fee"),
+                    fees.treasury.clone()
+                )
+                    .into_val(e),
+                (test.escrow_token.address.clone(), 1_i128).into_val(e)
+            )
+        ]
+    );
+}
+
+#[test]
+fn test_init_without_registry() {
+    let e = Env::default();
+    e.mock_all_auths();
+    let option = create_european_call_contract(&e);
+    let token = Address::random(&e);
+    let result = option.try_init_option(
+        &Address::random(&e),
+        &100_000_000,
+        &0,
+        &settlement_config(),
+        &OptionKind::Vanilla,
+        &Premium::Fixed(10),
+        &token,
+        &token_pair(&token, &token),
+        &100,
+        &Address::random(&e),
+    );
+    assert_eq!(result, Err(Ok(Error::NoAdmin)));
+}
+
+#[test]
+fn test_init_not_allowed() {
+    let test = OptionTest::setup();
+    test.set_price(12);
+    let registry = &test.registry;
+    let escrow_token = &test.escrow_token.address;
+    let underlying_token = &test.underlying_token.address;
+    registry.set_oracle_allowed(&test.oracle.address, &false);
+    registry.set_pair_allowed(escrow_token, underlying_token, &false);
+    let option = create_european_call_contract(&test.e);
+    let init = || {
+        test.try_init(
+            &option,
+            0,
+            settlement_config(),
+            OptionKind::Vanilla,
+            Premium::Fixed(10),
+            escrow_token,
+        )
+    };
+    assert_eq!(init(), Err(Error::OracleNotAllowed));
+
+    registry.set_oracle_allowed(&test.oracle.address, &true);
+    registry.set_pair_allowed(underlying_token, escrow_token, &true);
+    assert_eq!(init(), Err(Error::PairNotAllowed));
+
+    registry.set_pair_allowed(escrow_token, underlying_token, &true);
+    assert_eq!(init(), Ok(()));
+}
+
+#[test]
+fn test_seller_cannot_allow_oracle() {
+    let test = OptionTest::setup();
+    let e = &test.e;
+
+    // An oracle run by the seller, quoting whatever they like
+    let oracle_id = e.register_contract_wasm(None, oracle::WASM);
+    let oracle = oracle::Client::new(e, &oracle_id);
+    oracle.initialize(
+        &test.seller,
+        &oracle::Asset::Stellar(Address::random(e)),
+        &18,
+        &60,
+    );
+    oracle.add_price(
+        &0,
+        &oracle::Asset::Stellar(test.escrow_token.address.clone()),
+        &(10 * 10_i128.pow(18)),
+    );
+
+    // The seller's signature does not list it in the registry
+    e.mock_auths(&[MockAuth {
+        address: &test.seller,
+        invoke: &MockAuthInvoke {
+            contract: &test.registry.address,
+            fn_name: "set_oracle_allowed",
+            args: (oracle_id.clone(), true).into_val(e),
+            sub_invokes: &[],
+        },
+    }]);
+    assert!(test
+        .registry
+        .try_set_oracle_allowed(&oracle_id, &true)
+        .is_err());
+    assert!(!test.registry.is_oracle_allowed(&oracle_id));
+
+    // Nor does deploying and initializing the option contract themselves
+    e.mock_all_auths();
+    let option = create_european_call_contract(e);
+    let test = OptionTest { oracle, ..test };
+    let result = test.try_init(
+        &option,
+        0,
+        settlement_config(),
+        OptionKind::Vanilla,
+        Premium::Fixed(10),
+        &test.escrow_token.address,
+    );
+    assert_eq!(result, Err(Error::OracleNotAllowed));
+}
+
+#[test]
+fn test_init_without_price() {
+    let test = OptionTest::setup();
+    let option = create_european_call_contract(&test.e);
+    let init = || {
+        test.try_init(
+            &option,
+            0,
+            settlement_config(),
+            OptionKind::Vanilla,
+            Premium::Fixed(10),
+            &test.escrow_token.address,
+        )
+    };
+    assert_eq!(init(), Err(Error::PriceUnavailable));
+
+    test.set_price(0);
+    assert_eq!(init(), Err(Error::InvalidPrice));
+
+    test.set_price(12);
+    assert_eq!(init(), Ok(()));
+    assert_eq!(option.state(), OptionState::Open);
+}
+
+#[test]
+fn test_upgrade_admin() {
+    let test = OptionTest::setup();
+    let e = &test.e;
+    let option = &test.option;
+    assert_eq!(option.version(), 2);
+
+    // Only the registry admin can replace the code or migrate the data, not
+    // the seller who deployed the contract
+    let wasm_hash = BytesN::from_array(e, &[0; 32]);
+    e.mock_auths(&[
+        MockAuth {
+            address: &test.seller,
+            invoke: &MockAuthInvoke {
+                contract: &option.address,
+                fn_name: "upgrade",
+                args: (wasm_hash.clone(),).into_val(e),
+                sub_invokes: &[],
+            },
+        },
+        MockAuth {
+            address: &test.seller,
+            invoke: &MockAuthInvoke {
+                contract: &option.address,
+                fn_name: "migrate",
+                args: ().into_val(e),
+                sub_invokes: &[],
+            },
+        },
+    ]);
+    assert!(option.try_upgrade(&wasm_hash).is_err());
+    assert!(option.try_migrate().is_err());
+
+    e.mock_all_auths();
+    let admin = test.registry.admin();
+    option.migrate();
+    assert_eq!(
+        e.auths(),
+        std::vec![(
+            admin,
+            AuthorizedInvocation {
+                function: AuthorizedFunction::Contract((
+                    option.address.clone(),
+                    Symbol::new(e, "migrate"),
+                    ().into_val(e)
+                )),
+                sub_invocations: std::vec![]
+            }
+        )]
+    );
+    assert_eq!(option.version(), 2);
+}
+
+#[test]
+fn test_upgrade() {
+    let test = OptionTest::setup();
+    let e = &test.e;
+    test.option.buy_option(&test.buyer, &60, &10);
+    let option = test.option.get_option();
+
+    // The new code takes over the option and its positions
+    let admin = test.registry.admin();
+    let wasm_hash = e.install_contract_wasm(option_wasm::WASM);
+    test.option.upgrade(&wasm_hash);
+    assert_eq!(
+        e.auths(),
+        std::vec![(
+            admin,
+            AuthorizedInvocation {
+                function: AuthorizedFunction::Contract((
+                    test.option.address.clone(),
+                    Symbol::new(e, "upgrade"),
+                    (wasm_hash.clone(),).into_val(e)
+                )),
+                sub_invocations: std::vec![]
+            }
+        )]
+    );
+    test.option.migrate();
+    assert_eq!(test.option.version(), 2);
+    assert_eq!(test.option.get_option(), option);
+    assert_eq!(test.option.balance(&test.buyer), 60);
+
+    test.set_time(12345);
+    test.set_price(12);
+    test.option.exercise_option(&test.buyer);
+    assert_eq!(test.escrow_token.balance(&test.buyer), 94 + 60);
+}
+
+#[test]
+fn test_migrate_versions() {
+    let test = OptionTest::setup();
+    test.option.buy_option(&test.buyer, &60, &10);
+
+    let option = test.option.get_option();
+
+    // A contract deployed before the version key existed, whose option has
+    // no kind yet
+    test.e.as_contract(&test.option.address, || {
+        let storage = test.e.storage().instance();
+        storage.remove(&DataKey::Version);
+        storage.set(
+            &DataKey::OptionInfo,
+            &OptionInfoV1 {
+                seller: option.seller.clone(),
+                escrow_token: option.escrow_token.clone(),
+                underlying_token: option.underlying_token.clone(),
+                escrow_amount: option.escrow_amount,
+                strike_price: option.strike_price,
+                expiration_date: option.expiration_date,
+                settlement: option.settlement.clone(),
+                premium: option.premium.clone(),
+                premium_token: option.premium_token.clone(),
+                oracle_contract_id: option.oracle_contract_id.clone(),
+                oracle_decimals: option.oracle_decimals,
+                escrow_decimals: option.escrow_decimals,
+                underlying_decimals: option.underlying_decimals,
+            },
+        );
+    });
+    assert_eq!(test.option.version(), 0);
+    test.option.migrate();
+    assert_eq!(test.option.version(), 2);
+    assert_eq!(test.option.get_option(), option);
+    assert_eq!(test.option.balance(&test.buyer), 60);
+    assert_eq!(test.option.units_available(), 40);
+
+    // Data written by newer code cannot be read by this one
+    test.e.as_contract(&test.option.address, || {
+        test.e.storage().instance().set(&DataKey::Version, &3_u32);
+    });
+    assert_eq!(test.option.try_migrate(), Err(Ok(Error::InvalidVersion)));
+}
+
+#[test]
+fn test_upgrade_oracle() {
+    let test = OptionTest::setup();
+    test.option.buy_option(&test.buyer, &100, &10);
+    test.set_price(12);
+
+    // The option keeps settling against the upgraded oracle's stored prices
+    let wasm_hash = test.e.install_contract_wasm(oracle::WASM);
+    test.oracle.upgrade(&wasm_hash);
+    test.oracle.migrate();
+    assert_eq!(test.oracle.version(), 1);
+
+    test.option.exercise_option(&test.buyer);
+    assert_eq!(test.escrow_token.balance(&test.buyer), 190);
+}
+
+// Pays keepers a bounty of 3 escrow tokens out of a pool of 10
+fn fund_keeper_bounty(test: &OptionTest) {
+    let mut fees = fee_config(&test.e, 0, 0);
+    fees.keeper_bounty = 3;
+    test.registry.set_fees(&fees);
+    test.option.fund_bounty_pool(&test.seller, &10);
+    assert_eq!(test.option.bounty_pool(), 10);
+}
+
+#[test]
+fn test_settle_expired_in_the_money() {
+    let test = OptionTest::setup();
+    let e = &test.e;
+    let keeper = Address::random(e);
+    let holder = Address::random(e);
+    fund_keeper_bounty(&test);
+    test.option.buy_option(&test.buyer, &100, &10);
+    test.option.transfer(&test.buyer, &holder, &40);
+
+    test.set_time(12345);
+    // Nothing to settle before a price is published
+    assert_eq!(
+        test.option.try_settle(&keeper, &0, &10),
+        Err(Ok(Error::NothingToSettle))
+    );
+
+    test.set_price(12);
+    let options = vec![e, test.option.address.clone()];
+    assert_eq!(test.option.settle_expired(&keeper, &options, &10), 1);
+    let event = e.events().all().last().unwrap();
+    assert_eq!(
+        vec![e, event],
+        vec![
+            e,
+            (
+                test.option.address.clone(),
+                (
+                    symbol_short!("option"),
+                    symbol_short!("settled"),
+                    keeper.clone()
+                )
+                    .into_val(e),
+                (2_u32, 3_i128).into_val(e)
+            )
+        ]
+    );
+
+    // Both holders got their escrow and the seller the strike deposit
+    assert_eq!(test.escrow_token.balance(&test.buyer), 150);
+    assert_eq!(test.escrow_token.balance(&holder), 40);
+    assert_eq!(test.underlying_token.balance(&test.seller), 1000);
+    assert_eq!(test.escrow_token.balance(&keeper), 3);
+    assert_eq!(test.option.state(), OptionState::Exercised);
+
+    // The rest of the pool went back to the seller when the option closed
+    assert_eq!(test.option.bounty_pool(), 0);
+    assert_eq!(test.escrow_token.balance(&test.seller), 907);
+    assert_eq!(test.escrow_token.balance(&test.option.address), 0);
+    assert_eq!(
+        test.option.try_fund_bounty_pool(&test.seller, &10),
+        Err(Ok(Error::OptionClosed))
+    );
+
+    assert_eq!(test.option.settle_expired(&keeper, &options, &10), 0);
+}
+
+#[test]
+fn test_cancel_returns_bounty_pool() {
+    let test = OptionTest::setup();
+    let funder = Address::random(&test.e);
+    test.escrow_token.transfer(&test.buyer, &funder, &10);
+    test.option.fund_bounty_pool(&funder, &10);
+
+    test.option.withdraw();
+    assert_eq!(test.option.state(), OptionState::Cancelled);
+    assert_eq!(test.option.bounty_pool(), 0);
+    assert_eq!(test.escrow_token.balance(&test.seller), 1010);
+    assert_eq!(test.escrow_token.balance(&test.option.address), 0);
+}
+
+#[test]
+fn test_settle_expired_after_window() {
+    let test = OptionTest::setup();
+    let keeper = Address::random(&test.e);
+    test.option.buy_option(&test.buyer, &100, &10);
+    test.set_time(12345 + 3599);
+    test.set_price(8);
+
+    // Out of the money positions wait for the end of the window
+    assert_eq!(
+        test.option.try_settle(&keeper, &0, &10),
+        Err(Ok(Error::NothingToSettle))
+    );
+
+    test.set_time(12345 + 3600);
+    assert_eq!(test.option.settle(&keeper, &0, &10), 1);
+    assert_eq!(test.underlying_token.balance(&test.buyer), 10000);
+    assert_eq!(test.escrow_token.balance(&test.seller), 1010);
+    assert_eq!(test.option.balance(&test.buyer), 0);
+    assert_eq!(test.option.state(), OptionState::Expired);
+
+    // No bounty without a pool
+    assert_eq!(test.escrow_token.balance(&keeper), 0);
+}
+
+#[test]
+fn test_settle_expired_pay_at_exercise() {
+    let e = Env::default();
+    let test = OptionTest::setup_with(e, pay_at_exercise_config());
+    let keeper = Address::random(&test.e);
+    test.option.buy_option(&test.buyer, &100, &10);
+    test.set_time(12345);
+    test.set_price(12);
+
+    // The holder has to pay the strike, so only they can exercise
+    assert_eq!(
+        test.option.try_settle(&keeper, &0, &10),
+        Err(Ok(Error::NothingToSettle))
+    );
+    test.set_time(12345 + 3600);
+    assert_eq!(test.option.settle(&keeper, &0, &10), 1);
+    assert_eq!(test.escrow_token.balance(&test.seller), 1010);
+}
+
+#[test]
+fn test_settle_in_pages() {
+    let test = OptionTest::setup();
+    let keeper = Address::random(&test.e);
+    let holders = [
+        Address::random(&test.e),
+        Address::random(&test.e),
+        Address::random(&test.e),
+    ];
+    test.option.buy_option(&test.buyer, &100, &10);
+    for holder in holders.iter() {
+        test.option.transfer(&test.buyer, holder, &20);
+    }
+    // Receiving 0 units does not make a holder
+    test.option.transfer(&test.buyer, &keeper, &0);
+    assert_eq!(test.option.holder_count(), 4);
+
+    // Holders who sent all their units leave the list
+    test.option.transfer(&holders[0], &holders[1], &20);
+    assert_eq!(test.option.holder_count(), 3);
+
+    test.set_time(12345 + 3600);
+    test.set_price(8);
+    // Pages past the end settle nothing
+    assert_eq!(
+        test.option.try_settle(&keeper, &3, &10),
+        Err(Ok(Error::NothingToSettle))
+    );
+    assert_eq!(test.option.settle(&keeper, &0, &2), 2);
+    assert_eq!(test.option.holder_count(), 1);
+    assert_eq!(test.option.state(), OptionState::Purchased);
+    assert_eq!(test.option.settle(&keeper, &0, &2), 1);
+    assert_eq!(test.option.holder_count(), 0);
+    assert_eq!(test.option.state(), OptionState::Expired);
+    // Each holder got back the strike deposit of their units
+    assert_eq!(test.underlying_token.balance(&test.buyer), 9400);
+    assert_eq!(test.underlying_token.balance(&holders[1]), 400);
+    assert_eq!(test.underlying_token.balance(&holders[2]), 200);
+    assert_eq!(test.escrow_token.balance(&test.seller), 1010);
+}
+
+#[test]
+fn test_settle_expired_batch() {
+    let test = OptionTest::setup();
+    let e = &test.e;
+    let keeper = Address::random(e);
+    fund_keeper_bounty(&test);
+
+    // A second option on the same terms, with its own bounty pool
+    let other = create_european_call_contract(e);
+    test.set_price(12);
+    test.try_init(
+        &other,
+        60,
+        settlement_config(),
+        OptionKind::Vanilla,
+        Premium::Fixed(10),
+        &test.escrow_token.address,
+    )
+    .unwrap();
+    test.option.buy_option(&test.buyer, &50, &5);
+    other.buy_option(&test.buyer, &50, &5);
+    test.set_time(12345);
+    test.set_price(12);
+
+    // Unknown and unsettleable options are skipped
+    let options = vec![
+        e,
+        Address::random(e),
+        other.address.clone(),
+        test.option.address.clone(),
+    ];
+    assert_eq!(test.option.settle_expired(&keeper, &options, &10), 2);
+    assert_eq!(test.option.balance(&test.buyer), 0);
+    assert_eq!(other.balance(&test.buyer), 0);
+    assert_eq!(test.escrow_token.balance(&keeper), 3);
+    assert_eq!(test.option.bounty_pool(), 7);
+    assert_eq!(other.bounty_pool(), 0);
+}
+
+#[test]
+fn test_settle_before_expiry() {
+    let e = Env::default();
+    let test = OptionTest::setup_with_terms(
+        e,
+        settlement_config(),
+        3600,
+        Premium::Fixed(10),
+        OptionKind::Vanilla,
+    );
+    let keeper = Address::random(&test.e);
+    test.option.buy_option(&test.buyer, &100, &10);
+    assert_eq!(
+        test.option.try_settle(&keeper, &0, &10),
+        Err(Ok(Error::NotExpired))
+    );
+    assert_eq!(
+        test.option
+            .settle_expired(&keeper, &vec![&test.e, test.option.address.clone()], &10),
+        0
+    );
+}
+
+// Barrier option expiring an hour after creation, at a spot price of 10
+fn setup_barrier(barrier_type: BarrierType, level: i128) -> OptionTest<'static> {
+    let kind = OptionKind::Barrier(BarrierConfig {
+        barrier_type,
+        level: level * 10_000_000,
+    });
+    let test = OptionTest::setup_with_terms(
+        Env::default(),
+        settlement_config(),
+        3600,
+        Premium::Fixed(10),
+        kind,
+    );
+    test.option.buy_option(&test.buyer, &100, &10);
+    test
+}
+
+#[test]
+fn test_barrier_knocked_out() {
+    let test = setup_barrier(BarrierType::UpAndOut, 15);
+    test.set_time(13000);
+    test.set_price(15);
+    assert!(test.option.barrier_hit());
+
+    // In the money at expiration, but knocked out on the way
+    test.set_time(12345 + 3600);
+    test.set_price(12);
+    assert!(!test.option.is_exercisable());
+    assert_eq!(test.option.intrinsic_value(), 0);
+    assert_eq!(
+        test.option.try_exercise_option(&test.buyer),
+        Err(Ok(Error::KnockedOut))
+    );
+    // Worthless positions expire without waiting for the exercise window
+    let keeper = Address::random(&test.e);
+    assert_eq!(test.option.settle(&keeper, &0, &10), 1);
+    assert_eq!(test.underlying_token.balance(&test.buyer), 10000);
+    assert_eq!(test.escrow_token.balance(&test.seller), 1010);
+
+    // The breach stays recorded once the oracle has dropped it
+    for i in 0..10 {
+        test.set_time(12345 + 3600 + 60 * (i + 1));
+        test.set_price(12);
+    }
+    assert!(test.option.barrier_hit());
+}
+
+#[test]
+fn test_barrier_knocked_in() {
+    let test = setup_barrier(BarrierType::DownAndIn, 8);
+    test.set_time(13000);
+    test.set_price(9);
+    assert!(!test.option.barrier_hit());
+    test.set_time(13100);
+    test.set_price(8);
+    assert!(test.option.barrier_hit());
+
+    test.set_time(12345 + 3600);
+    test.set_price(12);
+    assert!(test.option.is_exercisable());
+    assert_eq!(test.option.intrinsic_value(), 200);
+    test.option.exercise_option(&test.buyer);
+    assert_eq!(test.escrow_token.balance(&test.buyer), 190);
+    assert_eq!(test.underlying_token.balance(&test.seller), 1000);
+}
+
+#[test]
+fn test_barrier_not_knocked_in() {
+    let test = setup_barrier(BarrierType::UpAndIn, 15);
+    test.set_time(13000);
+    test.set_price(14);
+
+    // Prices after expiration do not knock the option in
+    test.set_time(12345 + 3601);
+    test.set_price(16);
+    assert!(!test.option.is_exercisable());
+    assert_eq!(test.option.intrinsic_value(), 0);
+    assert_eq!(
+        test.option.try_exercise_option(&test.buyer),
+        Err(Ok(Error::NotKnockedIn))
+    );
+}
+
+#[test]
+fn test_trigger_barrier() {
+    let test = setup_barrier(BarrierType::UpAndOut, 15);
+    let e = &test.e;
+    test.set_time(13000);
+    test.set_price(12);
+    assert_eq!(
+        test.option.try_trigger_barrier(&0, &13000),
+        Err(Ok(Error::BarrierNotBreached))
+    );
+
+    test.set_time(13100);
+    test.set_price(16);
+    test.set_source_price(1, 16);
+    // Source 1 does not settle this option
+    assert_eq!(
+        test.option.try_trigger_barrier(&1, &13100),
+        Err(Ok(Error::BarrierNotBreached))
+    );
+    test.option.trigger_barrier(&0, &13100);
+    let event = e.events().all().last().unwrap();
+    assert_eq!(
+        vec![e, event],
+        vec![
+            e,
+            (
+                test.option.address.clone(),
+                (symbol_short!("option"), symbol_short!("barrier")).into_val(e),
+                (0_u32, 13100_u64, 16 * 10_i128.pow(18)).into_val(e)
+            )
+        ]
+    );
+
+    // The breach stays recorded once the oracle has dropped the observation
+    for i in 0..10 {
+        test.set_time(13200 + i * 60);
+        test.set_price(12);
+    }
+    test.set_time(12345 + 3600);
+    test.set_price(12);
+    assert_eq!(test.option.try_trigger_barrier(&0, &13100), Ok(Ok(())));
+    assert!(test.option.barrier_hit());
+    assert_eq!(
+        test.option.try_exercise_option(&test.buyer),
+        Err(Ok(Error::KnockedOut))
+    );
+}
+
+#[test]
+fn test_trigger_barrier_outside_life() {
+    let test = setup_barrier(BarrierType::DownAndOut, 8);
+    test.set_time(12345 + 3601);
+    test.set_price(7);
+    assert_eq!(
+        test.option.try_trigger_barrier(&0, &(12345 + 3601)),
+        Err(Ok(Error::BarrierNotBreached))
+    );
+    assert!(!test.option.barrier_hit());
+
+    let vanilla = OptionTest::setup();
+    assert_eq!(
+        vanilla.option.try_trigger_barrier(&0, &12345),
+        Err(Ok(Error::NoBarrier))
+    );
+    assert_eq!(vanilla.option.try_barrier_hit(), Err(Ok(Error::NoBarrier)));
+}
+
+#[test]
+fn test_barrier_terms() {
+    let test = OptionTest::setup();
+    test.set_price(10);
+    let option = create_european_call_contract(&test.e);
+    let init = |kind: OptionKind, premium: Premium, premium_token: &Address| {
+        test.try_init(
+            &option,
+            3600,
+            settlement_config(),
+            kind,
+            premium,
+            premium_token,
+        )
+    };
+    let barrier = |barrier_type, level: i128| {
+        OptionKind::Barrier(BarrierConfig {
+            barrier_type,
+            level: level * 10_000_000,
+        })
+    };
+    let escrow_token = &test.escrow_token.address;
+
+    // Not positive, or already reached by the spot price of 10
+    for kind in [
+        barrier(BarrierType::UpAndOut, 0),
+        barrier(BarrierType::UpAndIn, 10),
+        barrier(BarrierType::DownAndOut, 11),
+    ] {
+        assert_eq!(
+            init(kind, Premium::Fixed(10), escrow_token),
+            Err(Error::InvalidBarrier)
+        );
+    }
+    assert_eq!(
+        init(
+            barrier(BarrierType::UpAndOut, 15),
+            Premium::Quoted(quote_config()),
+            &test.underlying_token.address
+        ),
+        Err(Error::QuoteUnsupported)
+    );
+    assert_eq!(
+        init(
+            barrier(BarrierType::DownAndIn, 9),
+            Premium::Fixed(10),
+            escrow_token
+        ),
+        Ok(())
+    );
+}
+
+// Asian option with fixings 10, 20 and 30 minutes after 12345, expiring at
+// the last one
+fn setup_asian() -> OptionTest<'static> {
+    let e = Env::default();
+    let kind = OptionKind::Asian(AsianConfig {
+        fixings: vec![&e, 660, 1260, 1860],
+    });
+    let test = OptionTest::setup_with_terms(e, settlement_config(), 1800, Premium::Fixed(10), kind);
+    test.option.buy_option(&test.buyer, &100, &10);
+    test
+}
+
+#[test]
+fn test_asian_settles_on_average() {
+    let test = setup_asian();
+    let e = &test.e;
+    test.set_time(12345 + 600);
+    test.set_price(11);
+    assert_eq!(test.option.record_fixing(&0), 11 * 10_i128.pow(18));
+    let event = e.events().all().last().unwrap();
+    assert_eq!(
+        vec![e, event],
+        vec![
+            e,
+            (
+                test.option.address.clone(),
+                (symbol_short!("option"), symbol_short!("fixing")).into_val(e),
+                (0_u32, 11 * 10_i128.pow(18)).into_val(e)
+            )
+        ]
+    );
+
+    // Prices published after the fixing time do not count
+    test.set_time(12345 + 1190);
+    test.set_price(12);
+    test.set_time(12345 + 1250);
+    test.set_price(20);
+    assert_eq!(test.option.record_fixing(&1), 12 * 10_i128.pow(18));
+    assert_eq!(
+        test.option.try_record_fixing(&1),
+        Err(Ok(Error::FixingRecorded))
+    );
+
+    test.set_time(12345 + 1800);
+    test.set_price(13);
+    assert_eq!(
+        test.option.try_exercise_option(&test.buyer),
+        Err(Ok(Error::MissingFixings))
+    );
+    test.option.record_fixing(&2);
+    assert_eq!(test.option.fixing(&2), Some(13 * 10_i128.pow(18)));
+
+    // Settles on the average of 12 even though the spot price dropped to 8
+    test.set_time(12345 + 1860);
+    test.set_price(8);
+    test.option.exercise_option(&test.buyer);
+    assert_eq!(test.escrow_token.balance(&test.buyer), 190);
+    assert_eq!(test.underlying_token.balance(&test.seller), 1000);
+}
+
+#[test]
+fn test_asian_out_of_the_money() {
+    let test = setup_asian();
+    for (index, price) in [(0, 8), (1, 9), (2, 12)] {
+        test.set_time(12345 + 600 * (index + 1));
+        test.set_price(price);
+        test.option.record_fixing(&(index as u32));
+    }
+    assert_eq!(
+        test.option.try_exercise_option(&test.buyer),
+        Err(Ok(Error::OutOfTheMoney))
+    );
+}
+
+#[test]
+fn test_record_fixing_errors() {
+    let test = setup_asian();
+    test.set_time(12345 + 599);
+    test.set_price(11);
+    assert_eq!(
+        test.option.try_record_fixing(&0),
+        Err(Ok(Error::FixingNotDue))
+    );
+    assert_eq!(test.option.try_record_fixing(&3), Err(Ok(Error::NoFixings)));
+
+    // The last price is more than max_price_age older than the fixing
+    test.set_time(12345 + 1200);
+    assert_eq!(
+        test.option.try_record_fixing(&1),
+        Err(Ok(Error::PriceUnavailable))
+    );
+    assert_eq!(test.option.fixing(&1), None);
+
+    let vanilla = OptionTest::setup();
+    assert_eq!(
+        vanilla.option.try_record_fixing(&0),
+        Err(Ok(Error::NoFixings))
+    );
+}
+
+#[test]
+fn test_asian_terms() {
+    let test = OptionTest::setup();
+    let e = &test.e;
+    test.set_price(10);
+    let option = create_european_call_contract(e);
+    let init = |fixings: soroban_sdk::Vec<u64>, premium: Premium, premium_token: &Address| {
+        let kind = OptionKind::Asian(AsianConfig { fixings });
+        test.try_init(
+            &option,
+            1800,
+            settlement_config(),
+            kind,
+            premium,
+            premium_token,
+        )
+    };
+    let escrow_token = &test.escrow_token.address;
+
+    // Empty, unordered or after expiration
+    for fixings in [vec![e], vec![e, 600, 600], vec![e, 600, 1801]] {
+        assert_eq!(
+            init(fixings, Premium::Fixed(10), escrow_token),
+            Err(Error::InvalidFixings)
+        );
+    }
+    assert_eq!(
+        init(
+            vec![e, 1800],
+            Premium::Quoted(quote_config()),
+            &test.underlying_token.address
+        ),
+        Err(Error::QuoteUnsupported)
+    );
+    assert_eq!(
+        init(vec![e, 0, 1800], Premium::Fixed(10), escrow_token),
+        Ok(())
+    );
+}
+
+// Binary option paying 100 * 10 underlying tokens, bought in full
+fn setup_binary(payout: BinaryPayout) -> OptionTest<'static> {
+    let test = OptionTest::setup_with_terms(
+        Env::default(),
+        settlement_config(),
+        0,
+        Premium::Fixed(10),
+        OptionKind::Binary(payout),
+    );
+    // The seller keeps the escrow and posts the payout
+    assert_eq!(test.escrow_token.balance(&test.seller), 1000);
+    assert_eq!(test.underlying_token.balance(&test.seller), 0);
+    test.option.buy_option(&test.buyer, &100, &10);
+    test.set_time(12345);
+    test
+}
+
+#[test]
+fn test_binary_pays_fixed_amount() {
+    let test = setup_binary(BinaryPayout::Above);
+
+    // The buyer deposits nothing besides the premium
+    assert_eq!(test.underlying_token.balance(&test.buyer), 10000);
+    assert_eq!(test.underlying_token.balance(&test.option.address), 1000);
+
+    // The payout does not move with the price
+    test.set_price(12);
+    assert!(test.option.is_exercisable());
+    assert_eq!(test.option.intrinsic_value(), 1000);
+    test.set_time(12345 + 60);
+    test.set_price(100);
+    assert_eq!(test.option.intrinsic_value(), 1000);
+
+    test.option.exercise_option(&test.buyer);
+    assert_eq!(test.underlying_token.balance(&test.buyer), 11000);
+    assert_eq!(test.escrow_token.balance(&test.buyer), 90);
+    assert_eq!(test.underlying_token.balance(&test.option.address), 0);
+    assert_eq!(test.escrow_token.balance(&test.seller), 1010);
+    assert_eq!(test.option.state(), OptionState::Exercised);
+}
+
+#[test]
+fn test_binary_out_of_the_money() {
+    let test = setup_binary(BinaryPayout::Above);
+    test.set_price(8);
+    assert!(!test.option.is_exercisable());
+    assert_eq!(test.option.intrinsic_value(), 0);
+    assert_eq!(
+        test.option.try_exercise_option(&test.buyer),
+        Err(Ok(Error::OutOfTheMoney))
+    );
+
+    // The seller reclaims the payout once the window closes
+    test.set_time(12345 + 3600);
+    test.option.expire(&test.buyer);
+    assert_eq!(test.underlying_token.balance(&test.seller), 1000);
+    assert_eq!(test.underlying_token.balance(&test.buyer), 10000);
+    assert_eq!(test.escrow_token.balance(&test.seller), 1010);
+    assert_eq!(test.option.state(), OptionState::Expired);
+}
+
+#[test]
+fn test_binary_below() {
+    let test = setup_binary(BinaryPayout::Below);
+    test.set_price(8);
+    assert_eq!(test.option.intrinsic_value(), 1000);
+    test.option.exercise_option(&test.buyer);
+    assert_eq!(test.underlying_token.balance(&test.buyer), 11000);
+    assert_eq!(test.underlying_token.balance(&test.seller), 0);
+}
+
+#[test]
+fn test_binary_at_the_strike() {
+    let above = setup_binary(BinaryPayout::Above);
+    above.set_price(10);
+    assert!(above.option.is_exercisable());
+
+    let below = setup_binary(BinaryPayout::Below);
+    below.set_price(10);
+    assert!(!below.option.is_exercisable());
+}
+
+#[test]
+fn test_settle_expired_binary() {
+    let test = setup_binary(BinaryPayout::Above);
+    let keeper = Address::random(&test.e);
+    fund_keeper_bounty(&test);
+    test.set_price(12);
+    assert_eq!(test.option.settle(&keeper, &0, &10), 1);
+    assert_eq!(test.underlying_token.balance(&test.buyer), 11000);
+    assert_eq!(test.underlying_token.balance(&test.seller), 0);
+    assert_eq!(test.escrow_token.balance(&keeper), 3);
+    assert_eq!(test.option.state(), OptionState::Exercised);
+}
+
+// Bull call spread between strikes 10 and 12: the seller posts 200
+// underlying tokens and 100 units are bought
+fn setup_spread() -> OptionTest<'static> {
+    let test = OptionTest::setup_with_terms(
+        Env::default(),
+        settlement_config(),
+        0,
+        Premium::Fixed(10),
+        OptionKind::Spread(SpreadConfig {
+            upper_strike: 120_000_000,
+        }),
+    );
+    assert_eq!(test.underlying_token.balance(&test.seller), 800);
+    assert_eq!(test.escrow_token.balance(&test.seller), 1000);
+    test.option.buy_option(&test.buyer, &100, &10);
+    assert_eq!(test.underlying_token.balance(&test.buyer), 10000);
+    test.set_time(12345);
+    test
+}
+
+#[test]
+fn test_spread_exercise() {
+    let test = setup_spread();
+    test.set_price(11);
+    assert_eq!(test.option.intrinsic_value(), 100);
+    test.option.exercise_option(&test.buyer);
+
+    // The event reports the payoff in the underlying token, without a strike
+    let e = &test.e;
+    let event = e.events().all().last().unwrap();
+    assert_eq!(
+        vec![e, event],
+        vec![
+            e,
+            (
+                test.option.address.clone(),
+                (
+                    symbol_short!("option"),
+                    symbol_short!("exercised"),
+                    test.buyer.clone()
+                )
+                    .into_val(e),
+                (
+                    11 * 10_i128.pow(18),
+                    test.underlying_token.address.clone(),
+                    100_i128,
+                    0_i128
+                )
+                    .into_val(e)
+            )
+        ]
+    );
+
+    // 100 * (11 - 10) to the buyer, the rest of the collateral to the seller
+    assert_eq!(test.underlying_token.balance(&test.buyer), 10100);
+    assert_eq!(test.underlying_token.balance(&test.seller), 900);
+    assert_eq!(test.underlying_token.balance(&test.option.address), 0);
+    assert_eq!(test.escrow_token.balance(&test.buyer), 90);
+    assert_eq!(test.option.state(), OptionState::Exercised);
+}
+
+#[test]
+fn test_spread_payoff_capped() {
+    let test = setup_spread();
+    test.set_price(15);
+    assert_eq!(test.option.intrinsic_value(), 200);
+    test.option.exercise_option(&test.buyer);
+    assert_eq!(test.underlying_token.balance(&test.buyer), 10200);
+    assert_eq!(test.underlying_token.balance(&test.seller), 800);
+}
+
+#[test]
+fn test_spread_large_escrow() {
+    let test = OptionTest::setup();
+    let e = &test.e;
+    // A million escrow tokens at 7 decimals, against 18-decimal oracle prices
+    let escrow_amount = 10_i128.pow(13);
+    let max_loss = 2 * escrow_amount;
+    token::AdminClient::new(e, &test.escrow_token.address).mint(&test.seller, &escrow_amount);
+    token::AdminClient::new(e, &test.underlying_token.address).mint(&test.seller, &max_loss);
+    let seller_underlying = test.underlying_token.balance(&test.seller);
+
+    let option = create_european_call_contract(e);
+    test.set_price(10);
+    option.init_option(
+        &test.seller,
+        &100_000_000,
+        &60,
+        &settlement_config(),
+        &OptionKind::Spread(SpreadConfig {
+            upper_strike: 120_000_000,
+        }),
+        &Premium::Fixed(10),
+        &test.escrow_token.address,
+        &token_pair(&test.escrow_token.address, &test.underlying_token.address),
+        &escrow_amount,
+        &test.oracle.address,
+    );
+    option.buy_option(&test.buyer, &escrow_amount, &10);
+
+    // Capped at the upper strike: escrow_amount * (12 - 10)
+    test.set_time(12345);
+    test.set_price(15);
+    assert_eq!(option.intrinsic_value(), max_loss);
+    option.exercise_option(&test.buyer);
+    assert_eq!(test.underlying_token.balance(&test.buyer), 10000 + max_loss);
+    assert_eq!(
+        test.underlying_token.balance(&test.seller),
+        seller_underlying - max_loss
+    );
+}
+
+#[test]
+fn test_spread_out_of_the_money() {
+    let test = setup_spread();
+    test.set_price(8);
+    assert_eq!(
+        test.option.try_exercise_option(&test.buyer),
+        Err(Ok(Error::OutOfTheMoney))
+    );
+
+    test.set_time(12345 + 3600);
+    test.option.expire(&test.buyer);
+    assert_eq!(test.underlying_token.balance(&test.seller), 1000);
+    assert_eq!(test.underlying_token.balance(&test.buyer), 10000);
+    assert_eq!(test.option.state(), OptionState::Expired);
+}
+
+#[test]
+fn test_spread_partial_sale() {
+    let test = OptionTest::setup_with_terms(
+        Env::default(),
+        settlement_config(),
+        0,
+        Premium::Fixed(10),
+        OptionKind::Spread(SpreadConfig {
+            upper_strike: 120_000_000,
+        }),
+    );
+    let keeper = Address::random(&test.e);
+    let fees = fee_config(&test.e, 0, 1000);
+    test.registry.set_fees(&fees);
+    test.option.buy_option(&test.buyer, &40, &4);
+
+    // The collateral of the 60 unsold units goes back
+    test.option.withdraw();
+    assert_eq!(test.underlying_token.balance(&test.seller), 920);
+
+    test.set_time(12345);
+    test.set_price(15);
+    assert_eq!(test.option.settle(&keeper, &0, &10), 1);
+    // The capped payoff of 40 * (12 - 10), less the 10% exercise fee
+    assert_eq!(test.underlying_token.balance(&test.buyer), 10072);
+    assert_eq!(test.underlying_token.balance(&fees.treasury), 8);
+    assert_eq!(test.underlying_token.balance(&test.seller), 920);
+    assert_eq!(test.underlying_token.balance(&test.option.address), 0);
+    assert_eq!(test.option.state(), OptionState::Exercised);
+}
+
+#[test]
+fn test_spread_terms() {
+    let test = OptionTest::setup();
+    let e = &test.e;
+    test.set_price(10);
+    let option = create_european_call_contract(e);
+    for upper_strike in [100_000_000, 90_000_000] {
+        assert_eq!(
+            test.try_init(
+                &option,
+                0,
+                settlement_config(),
+                OptionKind::Spread(SpreadConfig { upper_strike }),
+                Premium::Fixed(10),
+                &test.escrow_token.address,
+            ),
+            Err(Error::InvalidSpread)
+        );
+    }
+}
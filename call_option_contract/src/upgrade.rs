@@ -0,0 +1,76 @@
+use crate::errors::Error;
+use crate::storage_types::{
+    DataKey, OptionInfo, OptionKind, Premium, SettlementConfig, INSTANCE_BUMP_AMOUNT,
+    STORAGE_VERSION,
+};
+use soroban_sdk::{contracttype, Address, Env};
+
+// OptionInfo up to version 1, before it had a kind
+#[derive(Clone)]
+#[contracttype(export = false)]
+pub struct OptionInfoV1 {
+    pub seller: Address,
+    pub escrow_token: Address,
+    pub underlying_token: Address,
+    pub escrow_amount: i128,
+    pub strike_price: i128,
+    pub expiration_date: u64,
+    pub settlement: SettlementConfig,
+    pub premium: Premium,
+    pub premium_token: Address,
+    pub oracle_contract_id: Address,
+    pub oracle_decimals: u32,
+    pub escrow_decimals: u32,
+    pub underlying_decimals: u32,
+}
+
+// Contracts deployed before the version key existed read as version 0
+pub fn read_version(e: &Env) -> u32 {
+    e.storage().instance().bump(INSTANCE_BUMP_AMOUNT);
+    e.storage().instance().get(&DataKey::Version).unwrap_or(0)
+}
+
+pub fn write_version(e: &Env, version: u32) {
+    e.storage().instance().bump(INSTANCE_BUMP_AMOUNT);
+    e.storage().instance().set(&DataKey::Version, &version);
+}
+
+// Converts the stored data to the layout of the running code, one version at
+// a time, and records STORAGE_VERSION. Refuses data written by newer code.
+pub fn migrate_storage(e: &Env) -> Result<(), Error> {
+    let version = read_version(e);
+    if version > STORAGE_VERSION {
+        return Err(Error::InvalidVersion);
+    }
+    if version < 2 {
+        migrate_option_kind(e);
+    }
+    write_version(e, STORAGE_VERSION);
+    Ok(())
+}
+
+// Version 2 added OptionInfo::kind: options written before are vanilla
+fn migrate_option_kind(e: &Env) {
+    let key = DataKey::OptionInfo;
+    let option = match e.storage().instance().get::<DataKey, OptionInfoV1>(&key) {
+        Some(option) => option,
+        None => return,
+    };
+    let option = OptionInfo {
+        seller: option.seller,
+        escrow_token: option.escrow_token,
+        underlying_token: option.underlying_token,
+        escrow_amount: option.escrow_amount,
+        strike_price: option.strike_price,
+        expiration_date: option.expiration_date,
+        settlement: option.settlement,
+        kind: OptionKind::Vanilla,
+        premium: option.premium,
+        premium_token: option.premium_token,
+        oracle_contract_id: option.oracle_contract_id,
+        oracle_decimals: option.oracle_decimals,
+        escrow_decimals: option.escrow_decimals,
+        underlying_decimals: option.underlying_decimals,
+    };
+    e.storage().instance().set(&key, &option);
+}
@@ -0,0 +1,55 @@
+use crate::errors::Error;
+use crate::storage_types::{DataKey, FeeConfig, INSTANCE_BUMP_AMOUNT};
+use soroban_sdk::{Address, Env};
+
+// Fees are in basis points of the amount they are taken from
+const MAX_FEE_BPS: u32 = 10_000;
+
+pub fn has_admin(e: &Env) -> bool {
+    e.storage().instance().has(&DataKey::Admin)
+}
+
+pub fn read_admin(e: &Env) -> Result<Address, Error> {
+    e.storage().instance().bump(INSTANCE_BUMP_AMOUNT);
+    e.storage()
+        .instance()
+        .get(&DataKey::Admin)
+        .ok_or(Error::NoAdmin)
+}
+
+pub fn write_admin(e: &Env, id: &Address) {
+    e.storage().instance().bump(INSTANCE_BUMP_AMOUNT);
+    e.storage().instance().set(&DataKey::Admin, id);
+}
+
+pub fn read_fees(e: &Env) -> Result<FeeConfig, Error> {
+    e.storage().instance().bump(INSTANCE_BUMP_AMOUNT);
+    e.storage()
+        .instance()
+        .get(&DataKey::Fees)
+        .ok_or(Error::NoAdmin)
+}
+
+pub fn write_fees(e: &Env, fees: &FeeConfig) -> Result<(), Error> {
+    if fees.premium_bps > MAX_FEE_BPS
+        || fees.bounty_bps > MAX_FEE_BPS
+        || fees.exercise_bps > MAX_FEE_BPS
+        || fees.keeper_bounty < 0
+    {
+        return Err(Error::InvalidFee);
+    }
+    e.storage().instance().bump(INSTANCE_BUMP_AMOUNT);
+    e.storage().instance().set(&DataKey::Fees, fees);
+    Ok(())
+}
+
+// Protocol's cut of an amount at the given rate, rounded down
+pub fn fee_amount(amount: i128, bps: u32) -> Result<i128, Error> {
+    if bps > MAX_FEE_BPS {
+        return Err(Error::InvalidFee);
+    }
+    amount
+        .checked_mul(i128::from(bps))
+        .map(|fee| fee / i128::from(MAX_FEE_BPS))
+        .ok_or(Error::ArithmeticOverflow)
+}
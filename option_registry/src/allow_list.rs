@@ -0,0 +1,51 @@
+use crate::storage_types::{DataKey, PERSISTENT_BUMP_AMOUNT};
+use soroban_sdk::{Address, Env};
+
+// Oracles and token pairs are denied unless governance approved them
+
+pub fn read_oracle_allowed(e: &Env, oracle: &Address) -> bool {
+    let key = DataKey::AllowedOracle(oracle.clone());
+    read_allowed(e, &key)
+}
+
+pub fn write_oracle_allowed(e: &Env, oracle: &Address, allowed: bool) {
+    let key = DataKey::AllowedOracle(oracle.clone());
+    write_allowed(e, &key, allowed);
+}
+
+pub fn read_pair_allowed(e: &Env, escrow_token: &Address, underlying_token: &Address) -> bool {
+    let key = pair_key(escrow_token, underlying_token);
+    read_allowed(e, &key)
+}
+
+pub fn write_pair_allowed(
+    e: &Env,
+    escrow_token: &Address,
+    underlying_token: &Address,
+    allowed: bool,
+) {
+    let key = pair_key(escrow_token, underlying_token);
+    write_allowed(e, &key, allowed);
+}
+
+fn pair_key(escrow_token: &Address, underlying_token: &Address) -> DataKey {
+    DataKey::AllowedPair(escrow_token.clone(), underlying_token.clone())
+}
+
+fn read_allowed(e: &Env, key: &DataKey) -> bool {
+    if let Some(allowed) = e.storage().persistent().get::<DataKey, bool>(key) {
+        e.storage().persistent().bump(key, PERSISTENT_BUMP_AMOUNT);
+        allowed
+    } else {
+        false
+    }
+}
+
+fn write_allowed(e: &Env, key: &DataKey, allowed: bool) {
+    if allowed {
+        e.storage().persistent().set(key, &true);
+        e.storage().persistent().bump(key, PERSISTENT_BUMP_AMOUNT);
+    } else {
+        e.storage().persistent().remove(key);
+    }
+}
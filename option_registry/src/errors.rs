@@ -0,0 +1,17 @@
+use soroban_sdk::contracterror;
+
+#[contracterror]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, PartialOrd, Ord)]
+#[repr(u32)]
+pub enum Error {
+    // initialize was already called
+    AlreadyInitialized = 1,
+    // initialize was not called yet
+    NoAdmin = 2,
+    // Fee rate above 100% or negative keeper bounty
+    InvalidFee = 3,
+    // Fee amount overflowed
+    ArithmeticOverflow = 4,
+    // Stored data was written by newer code
+    InvalidVersion = 5,
+}
@@ -0,0 +1,117 @@
+//! This contract holds the protocol settings shared by every European call
+//! option: the admin, the fees and the oracles and token pairs options may be
+//! written on
+
+#![no_std]
+
+mod admin;
+mod allow_list;
+mod errors;
+mod storage_types;
+mod test;
+mod upgrade;
+
+use crate::admin::{fee_amount, has_admin, read_admin, read_fees, write_admin, write_fees};
+use crate::allow_list::{
+    read_oracle_allowed, read_pair_allowed, write_oracle_allowed, write_pair_allowed,
+};
+use crate::errors::Error;
+use crate::storage_types::{FeeConfig, STORAGE_VERSION};
+use crate::upgrade::{migrate_storage, read_version, write_version};
+use soroban_sdk::{contract, contractimpl, Address, BytesN, Env};
+
+#[contract]
+pub struct OptionRegistry;
+
+#[contractimpl]
+impl OptionRegistry {
+    /// Sets the protocol admin and fees. Called once, with the admin's
+    /// signature, when the registry is deployed.
+    pub fn initialize(e: Env, admin: Address, fees: FeeConfig) -> Result<(), Error> {
+        if has_admin(&e) {
+            return Err(Error::AlreadyInitialized);
+        }
+        admin.require_auth();
+        write_fees(&e, &fees)?;
+        write_admin(&e, &admin);
+        write_version(&e, STORAGE_VERSION);
+        Ok(())
+    }
+
+    /// Replaces the contract code with the uploaded WASM `new_wasm_hash`,
+    /// keeping the contract id every option is built with, the settings and
+    /// the allow-lists. Call `migrate` afterwards so the new code can convert
+    /// data written by the old one. Only the admin can upgrade.
+    pub fn upgrade(e: Env, new_wasm_hash: BytesN<32>) -> Result<(), Error> {
+        read_admin(&e)?.require_auth();
+        e.deployer().update_current_contract_wasm(new_wasm_hash);
+        Ok(())
+    }
+
+    /// Converts the stored data to the layout of the running code. Only the
+    /// admin can migrate.
+    pub fn migrate(e: Env) -> Result<(), Error> {
+        read_admin(&e)?.require_auth();
+        migrate_storage(&e)
+    }
+
+    pub fn version(e: Env) -> u32 {
+        read_version(&e)
+    }
+
+    pub fn set_admin(e: Env, new_admin: Address) -> Result<(), Error> {
+        read_admin(&e)?.require_auth();
+        write_admin(&e, &new_admin);
+        Ok(())
+    }
+
+    /// Changes the protocol fees. They apply to purchases and exercises of
+    /// every option from now on.
+    pub fn set_fees(e: Env, fees: FeeConfig) -> Result<(), Error> {
+        read_admin(&e)?.require_auth();
+        write_fees(&e, &fees)
+    }
+
+    pub fn admin(e: Env) -> Result<Address, Error> {
+        read_admin(&e)
+    }
+
+    pub fn fees(e: Env) -> Result<FeeConfig, Error> {
+        read_fees(&e)
+    }
+
+    /// Fee at `bps` basis points of `amount`, rounded down. Options take
+    /// their fees through it.
+    pub fn fee_amount(_e: Env, amount: i128, bps: u32) -> Result<i128, Error> {
+        fee_amount(amount, bps)
+    }
+
+    /// Approves or revokes an oracle options may settle against. Revoking
+    /// only stops new options, existing ones keep their oracle.
+    pub fn set_oracle_allowed(e: Env, oracle: Address, allowed: bool) -> Result<(), Error> {
+        read_admin(&e)?.require_auth();
+        write_oracle_allowed(&e, &oracle, allowed);
+        Ok(())
+    }
+
+    /// Approves or revokes an escrow and underlying token pair options may be
+    /// written on.
+    pub fn set_pair_allowed(
+        e: Env,
+        escrow_token: Address,
+        underlying_token: Address,
+        allowed: bool,
+    ) -> Result<(), Error> {
+        read_admin(&e)?.require_auth();
+        write_pair_allowed(&e, &escrow_token, &underlying_token, allowed);
+        Ok(())
+    }
+
+    pub fn is_oracle_allowed(e: Env, oracle: Address) -> bool {
+        read_oracle_allowed(&e, &oracle)
+    }
+
+    pub fn is_pair_allowed(e: Env, escrow_token: Address, underlying_token: Address) -> bool {
+        read_pair_allowed(&e, &escrow_token, &underlying_token)
+    }
+}
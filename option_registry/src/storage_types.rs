@@ -0,0 +1,38 @@
+use soroban_sdk::{contracttype, Address};
+
+pub(crate) const INSTANCE_BUMP_AMOUNT: u32 = 34560; // 2 days
+pub(crate) const PERSISTENT_BUMP_AMOUNT: u32 = 518400; // 30 days
+
+// Layout of the data this code stores, recorded under DataKey::Version
+pub(crate) const STORAGE_VERSION: u32 = 1;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct FeeConfig {
+    // Receiver of the protocol fees
+    pub treasury: Address,
+    // Fee in basis points of every premium paid
+    pub premium_bps: u32,
+    // Share in basis points of the premium fee paid into the option's bounty
+    // pool instead of the treasury
+    pub bounty_bps: u32,
+    // Fee in basis points of the escrow delivered at exercise
+    pub exercise_bps: u32,
+    // Paid from an option's bounty pool, in premium token units, to whoever
+    // settles its expired positions with settle_expired
+    pub keeper_bounty: i128,
+}
+
+#[derive(Clone)]
+#[contracttype]
+pub enum DataKey {
+    // Protocol admin, allowed to change the fees and allow-lists
+    Admin,
+    Fees,
+    // Oracles options may settle against
+    AllowedOracle(Address),
+    // Escrow and underlying token pairs options may be written on
+    AllowedPair(Address, Address),
+    // Layout of the stored data, see STORAGE_VERSION
+    Version,
+}
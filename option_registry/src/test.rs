@@ -0,0 +1,271 @@
+#![cfg(test)]
+extern crate std;
+
+use crate::errors::Error;
+use crate::storage_types::{DataKey, FeeConfig};
+use crate::{OptionRegistry, OptionRegistryClient};
+use soroban_sdk::{
+    testutils::{Address as _, AuthorizedFunction, AuthorizedInvocation, MockAuth, MockAuthInvoke},
+    Address, BytesN, Env, IntoVal, Symbol,
+};
+
+// This contract's own WASM, built by `make test`, to upgrade the registry to
+mod registry_wasm {
+    soroban_sdk::contractimport!(
+        file = "target/wasm32-unknown-unknown/release/option_registry.wasm"
+    );
+}
+
+fn create_registry<'a>(e: &Env, admin: &Address) -> OptionRegistryClient<'a> {
+    let registry = OptionRegistryClient::new(e, &e.register_contract(None, OptionRegistry {}));
+    registry.initialize(admin, &fee_config(e, 0, 0));
+    registry
+}
+
+fn fee_config(e: &Env, premium_bps: u32, exercise_bps: u32) -> FeeConfig {
+    FeeConfig {
+        treasury: Address::random(e),
+        premium_bps,
+        bounty_bps: 0,
+        exercise_bps,
+        keeper_bounty: 0,
+    }
+}
+
+#[test]
+fn test_initialize() {
+    let e = Env::default();
+    let admin = Address::random(&e);
+    let registry = OptionRegistryClient::new(&e, &e.register_contract(None, OptionRegistry {}));
+    assert_eq!(registry.try_admin(), Err(Ok(Error::NoAdmin)));
+    assert_eq!(registry.try_fees(), Err(Ok(Error::NoAdmin)));
+
+    // The admin has to sign, so nobody else can claim the registry
+    let fees = fee_config(&e, 0, 0);
+    assert!(registry.try_initialize(&admin, &fees).is_err());
+
+    e.mock_all_auths();
+    registry.initialize(&admin, &fees);
+    assert_eq!(
+        e.auths(),
+        std::vec![(
+            admin.clone(),
+            AuthorizedInvocation {
+                function: AuthorizedFunction::Contract((
+                    registry.address.clone(),
+                    Symbol::new(&e, "initialize"),
+                    (admin.clone(), fees.clone()).into_val(&e)
+                )),
+                sub_invocations: std::vec![]
+            }
+        )]
+    );
+    assert_eq!(registry.admin(), admin);
+    assert_eq!(registry.fees(), fees);
+    assert_eq!(
+        registry.try_initialize(&Address::random(&e), &fees),
+        Err(Ok(Error::AlreadyInitialized))
+    );
+}
+
+#[test]
+fn test_fee_admin() {
+    let e = Env::default();
+    e.mock_all_auths();
+    let admin = Address::random(&e);
+    let registry = create_registry(&e, &admin);
+    let fees = fee_config(&e, 250, 0);
+    registry.set_fees(&fees);
+    assert_eq!(
+        e.auths(),
+        std::vec![(
+            admin.clone(),
+            AuthorizedInvocation {
+                function: AuthorizedFunction::Contract((
+                    registry.address.clone(),
+                    Symbol::new(&e, "set_fees"),
+                    (fees.clone(),).into_val(&e)
+                )),
+                sub_invocations: std::vec![]
+            }
+        )]
+    );
+    assert_eq!(registry.fees(), fees);
+    assert_eq!(registry.fee_amount(&1999, &250), 49);
+    assert_eq!(
+        registry.try_fee_amount(&1999, &10_001),
+        Err(Ok(Error::InvalidFee))
+    );
+    assert_eq!(
+        registry.try_fee_amount(&i128::MAX, &250),
+        Err(Ok(Error::ArithmeticOverflow))
+    );
+
+    assert_eq!(
+        registry.try_set_fees(&fee_config(&e, 10_001, 0)),
+        Err(Ok(Error::InvalidFee))
+    );
+    let mut bounty_share = fee_config(&e, 0, 0);
+    bounty_share.bounty_bps = 10_001;
+    assert_eq!(
+        registry.try_set_fees(&bounty_share),
+        Err(Ok(Error::InvalidFee))
+    );
+    let mut negative_bounty = fee_config(&e, 0, 0);
+    negative_bounty.keeper_bounty = -1;
+    assert_eq!(
+        registry.try_set_fees(&negative_bounty),
+        Err(Ok(Error::InvalidFee))
+    );
+
+    let new_admin = Address::random(&e);
+    registry.set_admin(&new_admin);
+    assert_eq!(registry.admin(), new_admin);
+}
+
+#[test]
+fn test_allow_list_admin() {
+    let e = Env::default();
+    e.mock_all_auths();
+    let admin = Address::random(&e);
+    let registry = create_registry(&e, &admin);
+    let oracle_id = Address::random(&e);
+    assert!(!registry.is_oracle_allowed(&oracle_id));
+
+    registry.set_oracle_allowed(&oracle_id, &true);
+    assert_eq!(
+        e.auths(),
+        std::vec![(
+            admin.clone(),
+            AuthorizedInvocation {
+                function: AuthorizedFunction::Contract((
+                    registry.address.clone(),
+                    Symbol::new(&e, "set_oracle_allowed"),
+                    (oracle_id.clone(), true).into_val(&e)
+                )),
+                sub_invocations: std::vec![]
+            }
+        )]
+    );
+    assert!(registry.is_oracle_allowed(&oracle_id));
+    registry.set_oracle_allowed(&oracle_id, &false);
+    assert!(!registry.is_oracle_allowed(&oracle_id));
+
+    // Pairs are directional
+    let escrow_token = Address::random(&e);
+    let underlying_token = Address::random(&e);
+    registry.set_pair_allowed(&escrow_token, &underlying_token, &true);
+    assert!(registry.is_pair_allowed(&escrow_token, &underlying_token));
+    assert!(!registry.is_pair_allowed(&underlying_token, &escrow_token));
+    registry.set_pair_allowed(&escrow_token, &underlying_token, &false);
+    assert!(!registry.is_pair_allowed(&escrow_token, &underlying_token));
+}
+
+#[test]
+fn test_upgrade_admin() {
+    let e = Env::default();
+    let admin = Address::random(&e);
+    e.mock_all_auths();
+    let registry = create_registry(&e, &admin);
+    assert_eq!(registry.version(), 1);
+
+    // Only the admin can replace the code or migrate the data
+    let other = Address::random(&e);
+    let wasm_hash = BytesN::from_array(&e, &[0; 32]);
+    e.mock_auths(&[
+        MockAuth {
+            address: &other,
+            invoke: &MockAuthInvoke {
+                contract: &registry.address,
+                fn_name: "upgrade",
+                args: (wasm_hash.clone(),).into_val(&e),
+                sub_invokes: &[],
+            },
+        },
+        MockAuth {
+            address: &other,
+            invoke: &MockAuthInvoke {
+                contract: &registry.address,
+                fn_name: "migrate",
+                args: ().into_val(&e),
+                sub_invokes: &[],
+            },
+        },
+    ]);
+    assert!(registry.try_upgrade(&wasm_hash).is_err());
+    assert!(registry.try_migrate().is_err());
+
+    e.mock_all_auths();
+    registry.migrate();
+    assert_eq!(
+        e.auths(),
+        std::vec![(
+            admin,
+            AuthorizedInvocation {
+                function: AuthorizedFunction::Contract((
+                    registry.address.clone(),
+                    Symbol::new(&e, "migrate"),
+                    ().into_val(&e)
+                )),
+                sub_invocations: std::vec![]
+            }
+        )]
+    );
+    assert_eq!(registry.version(), 1);
+}
+
+#[test]
+fn test_upgrade() {
+    let e = Env::default();
+    e.mock_all_auths();
+    let admin = Address::random(&e);
+    let registry = create_registry(&e, &admin);
+    let fees = fee_config(&e, 250, 100);
+    registry.set_fees(&fees);
+    let oracle = Address::random(&e);
+    registry.set_oracle_allowed(&oracle, &true);
+
+    // The new code keeps the admin, the fees and the allow-lists
+    let wasm_hash = e.install_contract_wasm(registry_wasm::WASM);
+    registry.upgrade(&wasm_hash);
+    assert_eq!(
+        e.auths(),
+        std::vec![(
+            admin.clone(),
+            AuthorizedInvocation {
+                function: AuthorizedFunction::Contract((
+                    registry.address.clone(),
+                    Symbol::new(&e, "upgrade"),
+                    (wasm_hash.clone(),).into_val(&e)
+                )),
+                sub_invocations: std::vec![]
+            }
+        )]
+    );
+    registry.migrate();
+    assert_eq!(registry.version(), 1);
+    assert_eq!(registry.admin(), admin);
+    assert_eq!(registry.fees(), fees);
+    assert!(registry.is_oracle_allowed(&oracle));
+}
+
+#[test]
+fn test_migrate_versions() {
+    let e = Env::default();
+    e.mock_all_auths();
+    let registry = create_registry(&e, &Address::random(&e));
+
+    // A registry deployed before the version key existed
+    e.as_contract(&registry.address, || {
+        e.storage().instance().remove(&DataKey::Version);
+    });
+    assert_eq!(registry.version(), 0);
+    registry.migrate();
+    assert_eq!(registry.version(), 1);
+
+    // Data written by newer code is refused
+    e.as_contract(&registry.address, || {
+        e.storage().instance().set(&DataKey::Version, &2_u32);
+    });
+    assert_eq!(registry.try_migrate(), Err(Ok(Error::InvalidVersion)));
+}
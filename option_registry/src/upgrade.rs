@@ -0,0 +1,25 @@
+use crate::errors::Error;
+use crate::storage_types::{DataKey, INSTANCE_BUMP_AMOUNT, STORAGE_VERSION};
+use soroban_sdk::Env;
+
+// Registries deployed before the version key existed read as version 0
+pub fn read_version(e: &Env) -> u32 {
+    e.storage().instance().bump(INSTANCE_BUMP_AMOUNT);
+    e.storage().instance().get(&DataKey::Version).unwrap_or(0)
+}
+
+pub fn write_version(e: &Env, version: u32) {
+    e.storage().instance().bump(INSTANCE_BUMP_AMOUNT);
+    e.storage().instance().set(&DataKey::Version, &version);
+}
+
+// Converts the stored data to the layout of the running code, one version at
+// a time, and records STORAGE_VERSION. Refuses data written by newer code.
+pub fn migrate_storage(e: &Env) -> Result<(), Error> {
+    let version = read_version(e);
+    if version > STORAGE_VERSION {
+        return Err(Error::InvalidVersion);
+    }
+    write_version(e, STORAGE_VERSION);
+    Ok(())
+}
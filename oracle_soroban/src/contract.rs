@@ -1,8 +1,8 @@
-use soroban_sdk::{contract, contractimpl, Address, Env, Map, Vec};
+use soroban_sdk::{contract, contractimpl, Address, BytesN, Env, Map, Vec};
 
 use crate::metadata;
 use crate::storage_types::{
-    Asset, DataKey, PriceData, INSTANCE_BUMP_AMOUNT, TEMPORARY_BUMP_AMOUNT,
+    Asset, DataKey, PriceData, INSTANCE_BUMP_AMOUNT, STORAGE_VERSION, TEMPORARY_BUMP_AMOUNT,
 };
 
 pub trait OracleTrait {
@@ -10,6 +10,16 @@ pub trait OracleTrait {
     fn has_admin(env: Env) -> bool;
     fn write_admin(env: Env, id: Address);
     fn read_admin(env: Env) -> Address;
+
+    /// Replace the contract code with the uploaded WASM `new_wasm_hash`,
+    /// keeping the contract id and its data. Call `migrate` afterwards so the
+    /// new code can convert data written by the old one.
+    fn upgrade(env: Env, new_wasm_hash: BytesN<32>);
+
+    /// Convert the stored data to the layout of the running code and record
+    /// its storage version.
+    fn migrate(env: Env);
+    fn version(env: Env) -> u32;
     fn add_price(env: Env, source: u32, asset: Asset, price: i128);
     //TODO add bulk prices
 
@@ -90,6 +100,23 @@ impl OracleTrait for Oracle {
         return metadata::read_admin(&env);
     }
 
+    fn upgrade(env: Env, new_wasm_hash: BytesN<32>) {
+        metadata::read_admin(&env).require_auth();
+        env.storage().instance().bump(INSTANCE_BUMP_AMOUNT);
+        env.deployer().update_current_contract_wasm(new_wasm_hash);
+    }
+
+    fn migrate(env: Env) {
+        metadata::read_admin(&env).require_auth();
+        env.storage().instance().bump(INSTANCE_BUMP_AMOUNT);
+        migrate(&env);
+    }
+
+    fn version(env: Env) -> u32 {
+        env.storage().instance().bump(INSTANCE_BUMP_AMOUNT);
+        return metadata::read_version(&env);
+    }
+
     fn add_price(env: Env, source: u32, asset: Asset, price: i128) {
         metadata::read_admin(&env).require_auth();
         env.storage().instance().bump(INSTANCE_BUMP_AMOUNT);
@@ -312,6 +339,17 @@ pub fn remove_prices(
     write_prices(env, &new_prices);
 }
 
+pub fn migrate(env: &Env) {
+    let version = metadata::read_version(env);
+    if version > STORAGE_VERSION {
+        panic!("storage version is newer than the contract")
+    }
+    // Nothing to convert yet: unversioned storage already has the current
+    // layout. Later layout changes convert the data here, one version at a
+    // time.
+    metadata::write_version(env, &STORAGE_VERSION);
+}
+
 pub fn read_prices(env: &Env) -> Map<u32, Map<Asset, Vec<PriceData>>> {
     let key = DataKey::Prices;
     env.storage().temporary().bump(&key, TEMPORARY_BUMP_AMOUNT);
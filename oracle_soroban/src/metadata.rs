@@ -1,4 +1,4 @@
-use crate::storage_types::{Asset, DataKey, PERSISTENT_BUMP_AMOUNT};
+use crate::storage_types::{Asset, DataKey, PERSISTENT_BUMP_AMOUNT, STORAGE_VERSION};
 use soroban_sdk::{Address, Env};
 
 pub fn has_admin(env: &Env) -> bool {
@@ -13,6 +13,15 @@ pub fn write_admin(env: &Env, id: &Address) {
     return env.storage().instance().set(&DataKey::Admin, id);
 }
 
+// Contracts deployed before the version key existed read as version 0
+pub fn read_version(env: &Env) -> u32 {
+    return env.storage().instance().get(&DataKey::Version).unwrap_or(0);
+}
+
+pub fn write_version(env: &Env, version: &u32) {
+    return env.storage().instance().set(&DataKey::Version, version);
+}
+
 pub fn write_base(env: &Env, base: &Asset) {
     let key = DataKey::Base;
     env.storage().persistent().set(&key, base);
@@ -66,4 +75,5 @@ pub fn write_metadata(env: &Env, admin: &Address, base: &Asset, decimals: &u32,
     write_base(env, base);
     write_decimals(env, decimals);
     write_resolution(env, resolution);
+    write_version(env, &STORAGE_VERSION);
 }
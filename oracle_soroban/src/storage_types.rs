@@ -4,6 +4,10 @@ pub(crate) const TEMPORARY_BUMP_AMOUNT: u32 = 17280; // 1 day
 pub(crate) const INSTANCE_BUMP_AMOUNT: u32 = 34560; // 2 days
 pub(crate) const PERSISTENT_BUMP_AMOUNT: u32 = 518400; // 30 days
 
+// Layout of the stored data written by this code. Bump it along with a step in
+// `migrate` whenever the layout of a key such as `DataKey::Prices` changes.
+pub(crate) const STORAGE_VERSION: u32 = 1;
+
 #[derive(Clone, Copy)]
 #[contracttype]
 pub enum DataKey {
@@ -12,6 +16,7 @@ pub enum DataKey {
     Decimals = 2,
     Resolution = 3,
     Prices = 4,
+    Version = 5,
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -1,8 +1,9 @@
 #![cfg(test)]
 
 use crate::contract::{Oracle, OracleClient};
-use crate::storage_types::Asset;
-use soroban_sdk::{testutils::Address as _, Address, Env, Vec};
+use crate::metadata;
+use crate::storage_types::{Asset, DataKey};
+use soroban_sdk::{testutils::Address as _, Address, BytesN, Env, Vec};
 extern crate std;
 
 fn is_asset_in_vec(asset: Asset, vec: &Vec<Asset>) -> bool {
@@ -506,3 +507,71 @@ fn test_prices_limit() {
     let lastprices = client.lastprices_by_source(&source, &asset, &30);
     assert_eq!(lastprices.len(), 10);
 }
+
+#[test]
+fn test_migrate() {
+    let env = Env::default();
+    let contract_id = env.register_contract(None, Oracle);
+    let client = OracleClient::new(&env, &contract_id);
+    let admin = Address::random(&env);
+    let base = Asset::Stellar(Address::random(&env));
+    client.initialize(&admin, &base, &18, &1);
+    assert_eq!(client.version(), 1);
+
+    // A contract deployed before the version key existed
+    env.as_contract(&contract_id, || {
+        env.storage().instance().remove(&DataKey::Version);
+    });
+    assert_eq!(client.version(), 0);
+
+    env.mock_all_auths();
+    let asset = Asset::Stellar(Address::random(&env));
+    client.add_price(&0, &asset, &13579);
+    client.migrate();
+    assert_eq!(client.version(), 1);
+    assert_eq!(client.lastprice(&asset).unwrap().price, 13579);
+
+    // Migrating again is a no-op
+    client.migrate();
+    assert_eq!(client.version(), 1);
+}
+
+#[test]
+#[should_panic]
+fn test_migrate_newer_version() {
+    let env = Env::default();
+    let contract_id = env.register_contract(None, Oracle);
+    let client = OracleClient::new(&env, &contract_id);
+    let admin = Address::random(&env);
+    let base = Asset::Stellar(Address::random(&env));
+    client.initialize(&admin, &base, &18, &1);
+    env.as_contract(&contract_id, || {
+        metadata::write_version(&env, &2);
+    });
+    env.mock_all_auths();
+    client.migrate();
+}
+
+#[test]
+#[should_panic]
+fn test_migrate_bad_auth() {
+    let env = Env::default();
+    let contract_id = env.register_contract(None, Oracle);
+    let client = OracleClient::new(&env, &contract_id);
+    let admin = Address::random(&env);
+    let base = Asset::Stellar(Address::random(&env));
+    client.initialize(&admin, &base, &18, &1);
+    client.migrate();
+}
+
+#[test]
+#[should_panic]
+fn test_upgrade_bad_auth() {
+    let env = Env::default();
+    let contract_id = env.register_contract(None, Oracle);
+    let client = OracleClient::new(&env, &contract_id);
+    let admin = Address::random(&env);
+    let base = Asset::Stellar(Address::random(&env));
+    client.initialize(&admin, &base, &18, &1);
+    client.upgrade(&BytesN::from_array(&env, &[0; 32]));
+}